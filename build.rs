@@ -0,0 +1,22 @@
+extern crate cc;
+
+use std::env;
+use std::path::PathBuf;
+
+const OPENNI2_INCLUDE: &str = "OPENNI2_INCLUDE";
+const OPENNI2_INCLUDE64: &str = "OPENNI2_INCLUDE64";
+
+fn main() {
+    let include_dir = env::var(OPENNI2_INCLUDE)
+        .or(env::var(OPENNI2_INCLUDE64))
+        .unwrap_or_else(|_| panic!("Required env var for OpenNI2 headers missing. Expected `{}` or `{}`.", OPENNI2_INCLUDE, OPENNI2_INCLUDE64));
+    println!("cargo:rerun-if-env-changed={}", OPENNI2_INCLUDE);
+    println!("cargo:rerun-if-env-changed={}", OPENNI2_INCLUDE64);
+    println!("cargo:rerun-if-changed=src/device.cpp");
+
+    cc::Build::new()
+        .cpp(true)
+        .include(PathBuf::from(include_dir))
+        .file("src/device.cpp")
+        .compile("nite2device");
+}
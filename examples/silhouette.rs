@@ -58,9 +58,9 @@ fn main() -> Result<(), Status> {
         let depth_pixels = depth_frame.pixels();
         depth_histogram(&mut histogram, depth_pixels);
         let user_map = user_frame.user_map();
-        assert_eq!(user_map.width, 320);
-        assert_eq!(user_map.height, 240);
-        for (i, (&user, &depth)) in user_map.pixels.iter().zip(depth_pixels).enumerate() {
+        assert_eq!(user_map.width(), 320);
+        assert_eq!(user_map.height(), 240);
+        for (i, (user, &depth)) in user_map.pixels().zip(depth_pixels).enumerate() {
             if user == 0 {
                 let brightness = histogram[depth as usize] as u32;
                 buffer[i] = brightness << 16 | brightness << 8 | brightness;
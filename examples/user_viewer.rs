@@ -3,10 +3,12 @@ extern crate nite2;
 extern crate piston_window;
 extern crate image;
 
+use std::collections::HashMap;
+use std::env;
 use piston_window::*;
 use image::{ImageBuffer};
 use openni2::OniDepthPixel;
-use nite2::{Status, UserTrackerManager, DepthPoint};
+use nite2::{Status, UserTrackerManager, UserEvent, DepthPoint};
 
 const WIDTH: usize = 320;
 const HEIGHT: usize = 240;
@@ -61,7 +63,10 @@ fn main() -> Result<(), Status> {
         .build()
         .unwrap();
 
-    // let mut glyphs = Glyphs::new("cour.ttf", window.factory.clone(), TextureSettings::new()).expect("font failed");
+    // Status labels and frame ids are drawn in the font passed as the first
+    // argument, or else in the cour.ttf next to Cargo.toml
+    let font = env::args().nth(1).unwrap_or_else(|| concat!(env!("CARGO_MANIFEST_DIR"), "/cour.ttf").to_string());
+    let mut glyphs = Glyphs::new(&font, window.factory.clone(), TextureSettings::new()).expect("font failed");
     let mut canvas = ImageBuffer::new(WIDTH as u32, HEIGHT as u32);
     let mut texture = Texture::from_image(
         &mut window.factory,
@@ -85,6 +90,7 @@ fn main() -> Result<(), Status> {
         [0., 1., 1.],
     ];
     let mut histogram = vec![0f32; 10000].into_boxed_slice();
+    let mut status_labels: HashMap<nite2::NiteUserId, &'static str> = HashMap::new();
 
     while let Some(e) = window.next() {
         if let Some(Button::Keyboard(key)) = e.press_args() {
@@ -100,7 +106,19 @@ fn main() -> Result<(), Status> {
             }
         }
         if let Some(_) = e.render_args() {
-            let user_frame = tracker.read_frame().expect("Couldn't read user tracker frame");
+            let (user_frame, events) = tracker.read_frame().expect("Couldn't read user tracker frame");
+            for event in events {
+                match event {
+                    UserEvent::UserLeft(id) => { status_labels.remove(&id); },
+                    UserEvent::UserEntered(id) => { status_labels.insert(id, "New"); },
+                    UserEvent::UserVisible(id) => { status_labels.insert(id, "Visible"); },
+                    UserEvent::UserOutOfView(id) => { status_labels.insert(id, "Out of scene"); },
+                    UserEvent::CalibrationStarted(id) => { status_labels.insert(id, "Calibrating..."); },
+                    UserEvent::CalibrationFailed(id, _) => { status_labels.insert(id, "Calibration failed"); },
+                    UserEvent::SkeletonTracked(id) => { status_labels.insert(id, "Tracking"); },
+                    UserEvent::SkeletonLost(id) => { status_labels.insert(id, "Lost"); },
+                }
+            }
 
             let users = user_frame.users();
 
@@ -111,10 +129,10 @@ fn main() -> Result<(), Status> {
             let depth_pixels = depth_frame.pixels();
             depth_histogram(&mut histogram, depth_pixels);
             let user_map = user_frame.user_map();
-            assert_eq!(user_map.width, WIDTH);
-            assert_eq!(user_map.height, HEIGHT);
+            assert_eq!(user_map.width(), WIDTH);
+            assert_eq!(user_map.height(), HEIGHT);
 
-            for ((&user, &depth), canvas_px) in user_map.pixels.iter().zip(depth_pixels).zip(canvas.pixels_mut()) {
+            for ((user, &depth), canvas_px) in user_map.pixels().zip(depth_pixels).zip(canvas.pixels_mut()) {
                 if user == 0 {
                     if viewer.draw_background {
                         let color = (histogram[depth as usize] * 256f32) as u8;
@@ -140,8 +158,9 @@ fn main() -> Result<(), Status> {
 
                 if viewer.draw_skeleton {
                     for skeleton in skeletons {
-                        for (j1, j2) in skeleton.limbs().into_iter() {
-                            line(WHITE, 1.0, [j1.position.x as f64, j1.position.y as f64, j2.position.x as f64, j2.position.y as f64], c.transform, g);
+                        for limb in skeleton.bones(1.0) {
+                            let (p1, p2) = (limb.parent().position(), limb.child().position());
+                            line(WHITE, 1.0, [p1.x as f64, p1.y as f64, p2.x as f64, p2.y as f64], c.transform, g);
                         }
                     }
                 }
@@ -159,13 +178,16 @@ fn main() -> Result<(), Status> {
                         line(WHITE, 1.0, [xmax as f64, ymin as f64, xmax as f64, ymax as f64], c.transform, g);
                     }
                     if viewer.draw_status_label {
-                        // text(WHITE, 16, "string", &mut glyphs, c.transform.trans(0.0, 0.0), g);
-                        // gotta keep track of user state in user manager (new, lost, calibrated, tracking, etc.)
+                        if let Some(label) = status_labels.get(&user.id()) {
+                            if let Ok(DepthPoint { x, y, .. }) = user.center_of_mass().into_depth(&depth_stream) {
+                                let _ = text(WHITE, 16, &format!("{} - {}", user.id(), label), &mut glyphs, c.transform.trans(x as f64, y as f64), g);
+                            }
+                        }
                     }
                 }
 
                 if viewer.draw_frame_id {
-                    // let _ = text(WHITE, 14, &format!("{}", user_frame.frame_index()), &mut glyphs, c.transform.trans(20., 20.), g);
+                    let _ = text(WHITE, 14, &format!("{}", user_frame.frame_index()), &mut glyphs, c.transform.trans(20., 20.), g);
                 }
             });
         }
@@ -0,0 +1,177 @@
+use std::fs::File;
+use std::io::{self, Write, BufWriter};
+use std::path::Path;
+use quaternion::Quaternion;
+use skeleton::LocalPose;
+use track::SkeletonTrack;
+use types::{JointType, WorldPoint};
+
+/// Writes a track as BVH, with a hierarchy rooted at `Torso` and one motion
+/// frame every `SkeletonTrack::frame_interval`. Positions are NiTE world
+/// coordinates in millimeters, and the rest pose is NiTE's calibration
+/// T-pose with bone lengths averaged over the track.
+///
+/// Frames before the first tracked skeleton and after the last are dropped,
+/// and frames where the skeleton isn't tracked repeat the last tracked pose.
+pub fn write_bvh<W: Write>(writer: &mut W, track: &SkeletonTrack) -> io::Result<()> {
+    let (interval, samples) = match track.fixed_rate_samples() {
+        Some(resampled) => resampled,
+        None => return Err(io::Error::new(io::ErrorKind::InvalidInput, "Writing BVH: track has no tracked skeletons")),
+    };
+    let mut last_pose = None;
+    let poses: Vec<LocalPose> = samples.iter().filter_map(|sample| {
+        if let Some(skeleton) = sample.skeleton {
+            last_pose = Some(skeleton.local_pose());
+        }
+        last_pose
+    }).collect();
+
+    let rest = match track.rest_pose() {
+        Some(rest) => rest,
+        None => return Err(io::Error::new(io::ErrorKind::InvalidInput, "Writing BVH: track has no tracked skeletons")),
+    };
+
+    let mut offsets = [WorldPoint::new(0.0, 0.0, 0.0); 15];
+    for joint in rest.joints() {
+        offsets[joint.joint_type as usize] = joint.offset;
+    }
+    // The root's position is entirely in its channels
+    offsets[JointType::Torso as usize] = WorldPoint::new(0.0, 0.0, 0.0);
+
+    writeln!(writer, "HIERARCHY")?;
+    write_joint(writer, JointType::Torso, &offsets, 0)?;
+
+    writeln!(writer, "MOTION")?;
+    writeln!(writer, "Frames: {}", poses.len())?;
+    writeln!(writer, "Frame Time: {:.6}", interval as f64 / 1_000_000.0)?;
+    for pose in &poses {
+        let root = pose.root().offset;
+        write!(writer, "{:.4} {:.4} {:.4}", root.x, root.y, root.z)?;
+        write_rotations(writer, pose, JointType::Torso)?;
+        writeln!(writer)?;
+    }
+    Ok(())
+}
+
+pub fn save_bvh<P: AsRef<Path>>(path: P, track: &SkeletonTrack) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    write_bvh(&mut writer, track)?;
+    writer.flush()
+}
+
+fn write_joint<W: Write>(writer: &mut W, joint_type: JointType, offsets: &[WorldPoint; 15], depth: usize) -> io::Result<()> {
+    let indent = "  ".repeat(depth);
+    let offset = offsets[joint_type as usize];
+    if depth == 0 {
+        writeln!(writer, "ROOT {:?}", joint_type)?;
+    } else {
+        writeln!(writer, "{}JOINT {:?}", indent, joint_type)?;
+    }
+    writeln!(writer, "{}{{", indent)?;
+    writeln!(writer, "{}  OFFSET {:.4} {:.4} {:.4}", indent, offset.x, offset.y, offset.z)?;
+    if depth == 0 {
+        writeln!(writer, "{}  CHANNELS 6 Xposition Yposition Zposition Zrotation Xrotation Yrotation", indent)?;
+    } else {
+        writeln!(writer, "{}  CHANNELS 3 Zrotation Xrotation Yrotation", indent)?;
+    }
+
+    let children = joint_type.children();
+    if children.is_empty() {
+        // NiTE has no joint past the head, hands and feet, so the end site
+        // continues a quarter of the way along the last bone.
+        let end = offset * 0.25;
+        writeln!(writer, "{}  End Site", indent)?;
+        writeln!(writer, "{}  {{", indent)?;
+        writeln!(writer, "{}    OFFSET {:.4} {:.4} {:.4}", indent, end.x, end.y, end.z)?;
+        writeln!(writer, "{}  }}", indent)?;
+    }
+    for &child in children {
+        write_joint(writer, child, offsets, depth + 1)?;
+    }
+    writeln!(writer, "{}}}", indent)
+}
+
+fn write_rotations<W: Write>(writer: &mut W, pose: &LocalPose, joint_type: JointType) -> io::Result<()> {
+    let (z, x, y) = zxy_euler_degrees(pose.joint(joint_type).rotation);
+    write!(writer, " {:.4} {:.4} {:.4}", z, x, y)?;
+    for &child in joint_type.children() {
+        write_rotations(writer, pose, child)?;
+    }
+    Ok(())
+}
+
+// BVH applies the channels in the order listed, so the rotation is
+// Rz(z) * Rx(x) * Ry(y).
+fn zxy_euler_degrees(rotation: Quaternion) -> (f32, f32, f32) {
+    let m = rotation.to_rotation_matrix();
+    let x = m[2][1].clamp(-1.0, 1.0).asin();
+    let (z, y) = if m[2][1].abs() < 0.9999 {
+        ((-m[0][1]).atan2(m[1][1]), (-m[2][0]).atan2(m[2][2]))
+    } else {
+        // Gimbal lock: z and y rotate around the same axis
+        (m[1][0].atan2(m[0][0]), 0.0)
+    };
+    (z.to_degrees(), x.to_degrees(), y.to_degrees())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mock::MockUser;
+    use types::SkeletonState;
+    use user_tracker::UserData;
+
+    #[test]
+    fn hierarchy_and_motion() {
+        let user = UserData::from(MockUser::new(1)
+            .skeleton_state(SkeletonState::Tracked)
+            .joint(JointType::Torso, WorldPoint::new(1.0, 2.0, 3.0), 1.0)
+            .joint(JointType::Neck, WorldPoint::new(1.0, 202.0, 3.0), 1.0));
+        let mut track = SkeletonTrack::new(1);
+        track.push(0, 0, user.skeleton().ok());
+        track.push(33_333, 1, None);
+        track.push(66_666, 2, user.skeleton().ok());
+
+        let mut bytes = Vec::new();
+        write_bvh(&mut bytes, &track).expect("writing to a Vec");
+        let bvh = String::from_utf8(bytes).expect("BVH is text");
+        let lines: Vec<&str> = bvh.lines().map(|line| line.trim()).collect();
+
+        assert_eq!(&lines[..5], &[
+            "HIERARCHY",
+            "ROOT Torso",
+            "{",
+            "OFFSET 0.0000 0.0000 0.0000",
+            "CHANNELS 6 Xposition Yposition Zposition Zrotation Xrotation Yrotation",
+        ]);
+        assert_eq!(&lines[5..9], &["JOINT Neck", "{", "OFFSET 0.0000 200.0000 0.0000", "CHANNELS 3 Zrotation Xrotation Yrotation"]);
+
+        // Joints are declared depth first, which is the order of their
+        // channels in each frame
+        let joints: Vec<&str> = lines.iter()
+            .filter_map(|line| line.strip_prefix("ROOT ").or_else(|| line.strip_prefix("JOINT ")))
+            .collect();
+        assert_eq!(joints, vec![
+            "Torso", "Neck", "Head", "LeftShoulder", "LeftElbow", "LeftHand",
+            "RightShoulder", "RightElbow", "RightHand", "LeftHip", "LeftKnee", "LeftFoot",
+            "RightHip", "RightKnee", "RightFoot",
+        ]);
+        let channels: usize = lines.iter()
+            .filter_map(|line| line.strip_prefix("CHANNELS "))
+            .map(|channels| channels.split(' ').next().and_then(|count| count.parse::<usize>().ok()).expect("channel count"))
+            .sum();
+        assert_eq!(channels, 6 + 14 * 3);
+
+        let motion = lines.iter().position(|&line| line == "MOTION").expect("MOTION section");
+        assert_eq!(lines[motion + 1], "Frames: 3");
+        assert_eq!(lines[motion + 2], "Frame Time: 0.033333");
+        let frames = &lines[motion + 3..];
+        assert_eq!(frames.len(), 3);
+        for frame in frames {
+            let values: Vec<f32> = frame.split(' ').map(|value| value.parse().expect("a number")).collect();
+            assert_eq!(values.len(), channels);
+            assert_eq!(&values[..3], &[1.0, 2.0, 3.0]);
+            assert!(values[3..].iter().all(|&angle| angle == 0.0));
+        }
+    }
+}
@@ -0,0 +1,265 @@
+use std::fs::File;
+use std::io::{self, Write, BufWriter};
+use std::path::Path;
+use track::SkeletonTrack;
+use types::JointType;
+
+const BLOCK_SIZE: usize = 512;
+const PARAMETER_START_BLOCK: usize = 2;
+const KEY: u8 = 0x50;
+const PROCESSOR_INTEL: u8 = 84;
+
+const POINT_GROUP: i8 = 1;
+const ANALOG_GROUP: i8 = 2;
+
+// Negative scale marks the point data as floats, in millimeters
+const SCALE: f32 = -1.0;
+
+// NiTE's confidences map onto residuals of 1 to 101 mm. C3D reserves a
+// residual of 0 for interpolated points and -1 for invalid ones.
+const MAX_RESIDUAL: f32 = 100.0;
+
+/// Writes a track as a C3D file (Intel, floating point) with one 3D point
+/// per `JointType`, at a fixed rate of one frame every
+/// `SkeletonTrack::frame_interval`. Points are NiTE world coordinates in
+/// millimeters. Each point's residual grows as its `positionConfidence`
+/// drops, and points are marked invalid in frames where the skeleton isn't
+/// tracked or NiTE has no confidence in the joint.
+///
+/// `POINT:FRAMES` is a signed 16 bit parameter, so tracks are limited to
+/// 32767 frames (about 18 minutes at 30 fps) rather than relying on readers
+/// to treat negative counts as unsigned.
+pub fn write_c3d<W: Write>(writer: &mut W, track: &SkeletonTrack) -> io::Result<()> {
+    let (interval, samples) = match track.fixed_rate_samples() {
+        Some(resampled) => resampled,
+        None => return Err(io::Error::new(io::ErrorKind::InvalidInput, "Writing C3D: track has no tracked skeletons")),
+    };
+    if samples.len() > i16::MAX as usize {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Writing C3D: too many frames for POINT:FRAMES"));
+    }
+    let rate = (1_000_000.0 / interval as f64) as f32;
+    let frame_count = samples.len() as u16;
+    let point_count = JointType::ALL.len() as u16;
+
+    let labels: Vec<String> = JointType::ALL.iter().map(|joint_type| format!("{:?}", joint_type)).collect();
+    let descriptions: Vec<String> = labels.iter().map(|_| format!("NiTE user {}", track.user_id())).collect();
+
+    // DATA_START depends on how many blocks the parameters take, which
+    // doesn't change with its value.
+    let parameter_blocks = point_parameters(rate, frame_count, 0, &labels, &descriptions).blocks();
+    let data_start = PARAMETER_START_BLOCK + parameter_blocks;
+    let parameters = point_parameters(rate, frame_count, data_start as u16, &labels, &descriptions);
+
+    let mut header = vec![0u8; BLOCK_SIZE];
+    header[0] = PARAMETER_START_BLOCK as u8;
+    header[1] = KEY;
+    header[2..4].copy_from_slice(&point_count.to_le_bytes());
+    header[6..8].copy_from_slice(&1u16.to_le_bytes());
+    header[8..10].copy_from_slice(&frame_count.to_le_bytes());
+    header[10..12].copy_from_slice(&10u16.to_le_bytes());
+    header[12..16].copy_from_slice(&SCALE.to_le_bytes());
+    header[16..18].copy_from_slice(&(data_start as u16).to_le_bytes());
+    header[20..24].copy_from_slice(&rate.to_le_bytes());
+    writer.write_all(&header)?;
+
+    writer.write_all(&[1, KEY, parameter_blocks as u8, PROCESSOR_INTEL])?;
+    writer.write_all(&parameters.data)?;
+    let parameter_size = 4 + parameters.data.len();
+    writer.write_all(&vec![0u8; parameter_blocks * BLOCK_SIZE - parameter_size])?;
+
+    let mut data = Vec::with_capacity(samples.len() * JointType::ALL.len() * 16);
+    for sample in &samples {
+        for &joint_type in JointType::ALL.iter() {
+            let values = match sample.skeleton.map(|skeleton| *skeleton.joint(joint_type)) {
+                Some(ref joint) if joint.position_confidence() > 0.0 => {
+                    let position = joint.position();
+                    let residual = 1.0 + ((1.0 - joint.position_confidence()).max(0.0) * MAX_RESIDUAL).round();
+                    [position.x, position.y, position.z, residual]
+                },
+                _ => [0.0, 0.0, 0.0, -1.0],
+            };
+            for value in values.iter() {
+                data.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+    }
+    let padding = (BLOCK_SIZE - data.len() % BLOCK_SIZE) % BLOCK_SIZE;
+    data.resize(data.len() + padding, 0);
+    writer.write_all(&data)
+}
+
+fn point_parameters(rate: f32, frame_count: u16, data_start: u16, labels: &[String], descriptions: &[String]) -> Parameters {
+    let mut parameters = Parameters::new();
+    parameters.group(POINT_GROUP, "POINT", "3D point parameters");
+    parameters.int("USED", POINT_GROUP, labels.len() as i16, "Number of 3D points");
+    parameters.float("SCALE", POINT_GROUP, SCALE, "Negative for floating point data");
+    parameters.float("RATE", POINT_GROUP, rate, "Frame rate");
+    parameters.int("DATA_START", POINT_GROUP, data_start as i16, "First block of point data");
+    parameters.int("FRAMES", POINT_GROUP, frame_count as i16, "Number of frames");
+    parameters.strings("UNITS", POINT_GROUP, &["mm".to_string()], "Point units");
+    parameters.strings("LABELS", POINT_GROUP, labels, "NiTE joints");
+    parameters.strings("DESCRIPTIONS", POINT_GROUP, descriptions, "");
+    parameters.group(ANALOG_GROUP, "ANALOG", "Analog parameters");
+    parameters.int("USED", ANALOG_GROUP, 0, "No analog channels");
+    parameters.float("RATE", ANALOG_GROUP, rate, "");
+    parameters.finish();
+    parameters
+}
+
+pub fn save_c3d<P: AsRef<Path>>(path: P, track: &SkeletonTrack) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    write_c3d(&mut writer, track)?;
+    writer.flush()
+}
+
+// The parameter section, after its 4 byte header. Each group or parameter
+// records the distance to the next one, and the last records 0.
+struct Parameters {
+    data: Vec<u8>,
+    last_offset: Option<usize>,
+}
+
+impl Parameters {
+    fn new() -> Parameters {
+        Parameters {
+            data: Vec::new(),
+            last_offset: None,
+        }
+    }
+
+    fn group(&mut self, id: i8, name: &str, description: &str) {
+        self.entry(-id, name, &[], description);
+    }
+
+    fn int(&mut self, name: &str, group: i8, value: i16, description: &str) {
+        let mut body = vec![2u8, 0];
+        body.extend_from_slice(&value.to_le_bytes());
+        self.entry(group, name, &body, description);
+    }
+
+    fn float(&mut self, name: &str, group: i8, value: f32, description: &str) {
+        let mut body = vec![4u8, 0];
+        body.extend_from_slice(&value.to_le_bytes());
+        self.entry(group, name, &body, description);
+    }
+
+    // A 2D character array, each string padded with spaces to the longest.
+    fn strings(&mut self, name: &str, group: i8, values: &[String], description: &str) {
+        let length = values.iter().map(|value| value.len()).max().unwrap_or(0).max(1);
+        let mut body = vec![(-1i8) as u8, 2, length as u8, values.len() as u8];
+        for value in values {
+            body.extend_from_slice(value.as_bytes());
+            body.resize(body.len() + length - value.len(), b' ');
+        }
+        self.entry(group, name, &body, description);
+    }
+
+    fn entry(&mut self, id: i8, name: &str, body: &[u8], description: &str) {
+        self.data.push(name.len() as u8);
+        self.data.push(id as u8);
+        self.data.extend_from_slice(name.as_bytes());
+        let next = 2 + body.len() + 1 + description.len();
+        self.last_offset = Some(self.data.len());
+        self.data.extend_from_slice(&(next as i16).to_le_bytes());
+        self.data.extend_from_slice(body);
+        self.data.push(description.len() as u8);
+        self.data.extend_from_slice(description.as_bytes());
+    }
+
+    fn finish(&mut self) {
+        if let Some(offset) = self.last_offset {
+            self.data[offset] = 0;
+            self.data[offset + 1] = 0;
+        }
+    }
+
+    // Number of blocks the section takes up, including its header.
+    fn blocks(&self) -> usize {
+        (4 + self.data.len()).div_ceil(BLOCK_SIZE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mock::MockUser;
+    use types::{SkeletonState, WorldPoint};
+    use user_tracker::UserData;
+
+    fn u16_at(bytes: &[u8], at: usize) -> u16 {
+        u16::from_le_bytes([bytes[at], bytes[at + 1]])
+    }
+
+    fn f32_at(bytes: &[u8], at: usize) -> f32 {
+        f32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
+    }
+
+    #[test]
+    fn short_track() {
+        let user = UserData::from(MockUser::new(1)
+            .skeleton_state(SkeletonState::Tracked)
+            .joint(JointType::Head, WorldPoint::new(1.0, 2.0, 3.0), 1.0)
+            .joint(JointType::Neck, WorldPoint::new(4.0, 5.0, 6.0), 0.5));
+        let mut track = SkeletonTrack::new(1);
+        track.push(0, 0, user.skeleton().ok());
+        track.push(33_333, 1, None);
+        track.push(66_666, 2, user.skeleton().ok());
+
+        let mut bytes = Vec::new();
+        write_c3d(&mut bytes, &track).expect("writing to a Vec");
+        assert_eq!(bytes.len() % BLOCK_SIZE, 0);
+
+        // Header words
+        assert_eq!(&bytes[..2], &[PARAMETER_START_BLOCK as u8, KEY]);
+        assert_eq!(u16_at(&bytes, 2), 15);
+        assert_eq!(u16_at(&bytes, 6), 1);
+        assert_eq!(u16_at(&bytes, 8), 3);
+        assert_eq!(f32_at(&bytes, 12), SCALE);
+        let data_start = u16_at(&bytes, 16) as usize;
+        assert!((f32_at(&bytes, 20) - 30.0).abs() < 0.01);
+
+        // Parameter section header, then the chain of groups and parameters
+        let section = BLOCK_SIZE * (PARAMETER_START_BLOCK - 1);
+        assert_eq!(&bytes[section..section + 2], &[1, KEY]);
+        assert_eq!(bytes[section + 3], PROCESSOR_INTEL);
+        assert_eq!(data_start, PARAMETER_START_BLOCK + bytes[section + 2] as usize);
+        let mut at = section + 4;
+        let mut names = Vec::new();
+        loop {
+            let name_length = (bytes[at] as i8).unsigned_abs() as usize;
+            let group = bytes[at + 1] as i8;
+            let name = String::from_utf8(bytes[at + 2..at + 2 + name_length].to_vec()).expect("ASCII name");
+            let next_at = at + 2 + name_length;
+            let next = u16_at(&bytes, next_at) as usize;
+            if group == POINT_GROUP && (name == "FRAMES" || name == "DATA_START") {
+                // An integer: type 2, no dimensions, then the value
+                assert_eq!(&bytes[next_at + 2..next_at + 4], &[2, 0]);
+                let value = u16_at(&bytes, next_at + 4) as usize;
+                assert_eq!(value, if name == "FRAMES" { 3 } else { data_start });
+            }
+            names.push((group, name));
+            if next == 0 {
+                break;
+            }
+            at = next_at + next;
+            assert!(at < (data_start - 1) * BLOCK_SIZE, "parameter chain runs into the data");
+        }
+        assert_eq!(names.first(), Some(&(-POINT_GROUP, "POINT".to_string())));
+        assert_eq!(names.last(), Some(&(ANALOG_GROUP, "RATE".to_string())));
+        assert!(names.contains(&(POINT_GROUP, "LABELS".to_string())));
+
+        // x, y, z, residual for every joint of every frame
+        let data = (data_start - 1) * BLOCK_SIZE;
+        let point = |frame: usize, joint: JointType| data + (frame * JointType::ALL.len() + joint as usize) * 16;
+        let head = point(0, JointType::Head);
+        assert_eq!([f32_at(&bytes, head), f32_at(&bytes, head + 4), f32_at(&bytes, head + 8)], [1.0, 2.0, 3.0]);
+        assert_eq!(f32_at(&bytes, head + 12), 1.0);
+        assert_eq!(f32_at(&bytes, point(0, JointType::Neck) + 12), 51.0);
+        // No confidence in the torso, and nothing tracked in the middle frame
+        assert_eq!(f32_at(&bytes, point(0, JointType::Torso) + 12), -1.0);
+        for &joint in JointType::ALL.iter() {
+            assert_eq!(f32_at(&bytes, point(1, joint) + 12), -1.0);
+        }
+        assert_eq!(f32_at(&bytes, point(2, JointType::Head) + 12), 1.0);
+    }
+}
@@ -0,0 +1,305 @@
+use std::collections::HashMap;
+use openni2::Stream;
+use frame_source::DepthMap;
+use mask::Mask;
+use types::{DepthPoint, WorldPoint, Status};
+
+/// A closed outline in depth image coordinates. Points are on pixel
+/// corners, so a single pixel at (x, y) is outlined by (x, y) and
+/// (x + 1, y + 1). `z` is 0 until filled in by `with_depth`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Contour {
+    points: Vec<DepthPoint>,
+    hole: bool,
+}
+
+impl Contour {
+    /// Clockwise on screen for outer boundaries, counterclockwise for holes.
+    pub fn points(&self) -> &[DepthPoint] {
+        &self.points
+    }
+
+    pub fn is_hole(&self) -> bool {
+        self.hole
+    }
+
+    /// Removes points that are within `epsilon` pixels of the simplified
+    /// outline (Ramer-Douglas-Peucker). A negative `epsilon` counts as 0.0.
+    pub fn simplify(&self, epsilon: f32) -> Contour {
+        if self.points.len() < 4 {
+            return self.clone();
+        }
+        let epsilon = epsilon.max(0.0);
+        // Split the loop at the point farthest from the first, and simplify
+        // each half as an open polyline.
+        let first = self.points[0];
+        let (farthest, _) = self.points.iter().enumerate()
+            .map(|(i, p)| (i, planar(*p).distance(planar(first))))
+            .fold((0, 0.0), |best, candidate| if candidate.1 > best.1 { candidate } else { best });
+
+        let mut points = Vec::new();
+        simplify_polyline(&self.points[..=farthest], epsilon, &mut points);
+        points.pop();
+        let mut second_half = self.points[farthest..].to_vec();
+        second_half.push(first);
+        simplify_polyline(&second_half, epsilon, &mut points);
+        points.pop();
+
+        Contour { points, hole: self.hole }
+    }
+
+    /// Rounds off corners by Chaikin corner cutting, doubling the number of
+    /// points on each iteration.
+    pub fn smooth(&self, iterations: usize) -> Contour {
+        let mut points = self.points.clone();
+        for _ in 0..iterations {
+            if points.len() < 3 {
+                break;
+            }
+            points = (0..points.len()).flat_map(|i| {
+                let p = points[i];
+                let q = points[(i + 1) % points.len()];
+                vec![p.lerp(q, 0.25), p.lerp(q, 0.75)]
+            }).collect();
+        }
+        Contour { points, hole: self.hole }
+    }
+
+    /// Fills in each point's `z` from the nearest valid depth pixel touching
+    /// its corner. Where several do, the closest to the sensor is used,
+    /// since the user is in front of whatever is behind their outline.
+    pub fn with_depth(&self, depth: &DepthMap) -> Contour {
+        let points = self.points.iter().map(|p| {
+            let (x, y) = (p.x.round() as isize, p.y.round() as isize);
+            let z = [(x - 1, y - 1), (x, y - 1), (x - 1, y), (x, y)].iter()
+                .filter(|&&(px, py)| px >= 0 && py >= 0 && (px as usize) < depth.width && (py as usize) < depth.height)
+                .map(|&(px, py)| depth.pixels[py as usize * depth.width + px as usize])
+                .filter(|&z| z > 0)
+                .min()
+                .unwrap_or(0);
+            DepthPoint { x: p.x, y: p.y, z: z as f32 }
+        }).collect();
+        Contour { points, hole: self.hole }
+    }
+
+    /// Converts the points to world coordinates. They need depth values
+    /// first; see `with_depth`.
+    pub fn to_world(&self, depth_stream: &Stream) -> Result<Vec<WorldPoint>, Status> {
+        self.points.iter().map(|p| p.into_world(depth_stream)).collect()
+    }
+}
+
+impl Mask {
+    /// Outlines of every 4-connected region of set pixels, and of the holes
+    /// inside them, by following the pixel edges between set and unset
+    /// pixels. Straight runs are collapsed to their end points.
+    pub fn contours(&self) -> Vec<Contour> {
+        // Directed pixel edges with the set pixel on the right (on screen)
+        let mut edges: Vec<((i32, i32), (i32, i32))> = Vec::new();
+        for y in 0..self.height() {
+            for x in 0..self.width() {
+                if !self.get(x, y) {
+                    continue;
+                }
+                let (xi, yi) = (x as i32, y as i32);
+                if y == 0 || !self.get(x, y - 1) {
+                    edges.push(((xi, yi), (xi + 1, yi)));
+                }
+                if !self.get(x + 1, y) {
+                    edges.push(((xi + 1, yi), (xi + 1, yi + 1)));
+                }
+                if !self.get(x, y + 1) {
+                    edges.push(((xi + 1, yi + 1), (xi, yi + 1)));
+                }
+                if x == 0 || !self.get(x - 1, y) {
+                    edges.push(((xi, yi + 1), (xi, yi)));
+                }
+            }
+        }
+
+        let mut outgoing: HashMap<(i32, i32), Vec<usize>> = HashMap::new();
+        for (i, edge) in edges.iter().enumerate() {
+            outgoing.entry(edge.0).or_default().push(i);
+        }
+
+        let mut used = vec![false; edges.len()];
+        let mut contours = Vec::new();
+        for start in 0..edges.len() {
+            if used[start] {
+                continue;
+            }
+            let mut vertices = Vec::new();
+            let mut current = start;
+            loop {
+                used[current] = true;
+                let (from, to) = edges[current];
+                vertices.push(from);
+                let direction = (to.0 - from.0, to.1 - from.1);
+                // Where diagonal pixels meet there are two ways on; turning
+                // right keeps them in separate outlines.
+                let next = outgoing[&to].iter()
+                    .cloned()
+                    .filter(|&i| !used[i] || i == start)
+                    .max_by_key(|&i| {
+                        let (a, b) = edges[i];
+                        direction.0 * (b.1 - a.1) - direction.1 * (b.0 - a.0)
+                    });
+                match next {
+                    Some(next) if next != start => current = next,
+                    _ => break,
+                }
+            }
+            contours.push(contour_from_vertices(&vertices));
+        }
+        contours
+    }
+}
+
+fn contour_from_vertices(vertices: &[(i32, i32)]) -> Contour {
+    let n = vertices.len();
+    let points: Vec<DepthPoint> = (0..n).filter(|&i| {
+        let prev = vertices[(i + n - 1) % n];
+        let here = vertices[i];
+        let next = vertices[(i + 1) % n];
+        (here.0 - prev.0, here.1 - prev.1) != (next.0 - here.0, next.1 - here.1)
+    }).map(|i| DepthPoint::new(vertices[i].0 as f32, vertices[i].1 as f32, 0.0)).collect();
+
+    // Shoelace area is positive for clockwise loops on screen (y down)
+    let area: i64 = (0..n).map(|i| {
+        let (a, b) = (vertices[i], vertices[(i + 1) % n]);
+        a.0 as i64 * b.1 as i64 - b.0 as i64 * a.1 as i64
+    }).sum();
+    Contour { points, hole: area < 0 }
+}
+
+// Ramer-Douglas-Peucker over an open polyline, appending to `out`
+// including both end points.
+fn simplify_polyline(points: &[DepthPoint], epsilon: f32, out: &mut Vec<DepthPoint>) {
+    let first = points[0];
+    let last = points[points.len() - 1];
+    if points.len() < 3 {
+        out.push(first);
+        out.push(last);
+        return;
+    }
+    let (index, distance) = points.iter().enumerate()
+        .take(points.len() - 1)
+        .skip(1)
+        .map(|(i, p)| (i, segment_distance(*p, first, last)))
+        .fold((0, 0.0), |best, candidate| if candidate.1 > best.1 { candidate } else { best });
+    if distance > epsilon {
+        simplify_polyline(&points[..=index], epsilon, out);
+        out.pop();
+        simplify_polyline(&points[index..], epsilon, out);
+    } else {
+        out.push(first);
+        out.push(last);
+    }
+}
+
+fn segment_distance(p: DepthPoint, a: DepthPoint, b: DepthPoint) -> f32 {
+    let (p, a, b) = (planar(p), planar(a), planar(b));
+    let ab = b - a;
+    let length_squared = ab.dot(ab);
+    if length_squared == 0.0 {
+        return p.distance(a);
+    }
+    let t = ((p - a).dot(ab) / length_squared).clamp(0.0, 1.0);
+    p.distance(a + ab * t)
+}
+
+fn planar(p: DepthPoint) -> DepthPoint {
+    DepthPoint::new(p.x, p.y, 0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mask(rows: &[&str]) -> Mask {
+        let pixels = rows.iter().flat_map(|row| row.chars().map(|c| c == '#')).collect();
+        Mask::from_pixels(rows[0].len(), rows.len(), pixels)
+    }
+
+    fn corners(contour: &Contour) -> Vec<(f32, f32)> {
+        contour.points().iter().map(|p| (p.x, p.y)).collect()
+    }
+
+    fn disc(size: usize, radius: f32) -> Mask {
+        let center = size as f32 / 2.0;
+        let pixels = (0..size * size).map(|i| {
+            let (x, y) = ((i % size) as f32 + 0.5 - center, (i / size) as f32 + 0.5 - center);
+            x * x + y * y < radius * radius
+        }).collect();
+        Mask::from_pixels(size, size, pixels)
+    }
+
+    #[test]
+    fn single_pixel() {
+        let contours = mask(&["...", ".#.", "..."]).contours();
+        assert_eq!(contours.len(), 1);
+        assert!(!contours[0].is_hole());
+        assert_eq!(corners(&contours[0]), vec![(1.0, 1.0), (2.0, 1.0), (2.0, 2.0), (1.0, 2.0)]);
+    }
+
+    #[test]
+    fn ring_with_hole_and_diagonal_pixel() {
+        let contours = mask(&[
+            "#####.",
+            "#...#.",
+            "#...#.",
+            "#####.",
+            ".....#",
+        ]).contours();
+        assert_eq!(contours.len(), 3);
+
+        let outer: Vec<&Contour> = contours.iter().filter(|contour| !contour.is_hole()).collect();
+        let holes: Vec<&Contour> = contours.iter().filter(|contour| contour.is_hole()).collect();
+        assert_eq!(outer.len(), 2);
+        assert_eq!(holes.len(), 1);
+        assert_eq!(corners(outer[0]), vec![(0.0, 0.0), (5.0, 0.0), (5.0, 4.0), (0.0, 4.0)]);
+        // The pixel touching the ring's corner gets an outline of its own
+        assert_eq!(corners(outer[1]), vec![(5.0, 4.0), (6.0, 4.0), (6.0, 5.0), (5.0, 5.0)]);
+        let mut hole = corners(holes[0]);
+        hole.sort_by(|a, b| a.partial_cmp(b).expect("corners are finite"));
+        assert_eq!(hole, vec![(1.0, 1.0), (1.0, 3.0), (4.0, 1.0), (4.0, 3.0)]);
+    }
+
+    #[test]
+    fn empty_mask() {
+        assert!(Mask::new(4, 4).contours().is_empty());
+    }
+
+    #[test]
+    fn simplify() {
+        let contour = disc(40, 12.0).contours().remove(0);
+        let simplified = contour.simplify(1.0);
+        assert!(simplified.points().len() < contour.points().len());
+        assert!(simplified.points().len() >= 8);
+        for p in simplified.points() {
+            assert!(contour.points().contains(p));
+        }
+        assert_eq!(contour.simplify(0.0).points().len(), contour.points().len());
+    }
+
+    #[test]
+    fn simplify_negative_epsilon() {
+        let contour = disc(20, 6.0).contours().remove(0);
+        assert_eq!(contour.simplify(-1.0), contour.simplify(0.0));
+        let square = mask(&["##", "##"]).contours().remove(0);
+        assert_eq!(square.simplify(-1.0), square);
+    }
+
+    #[test]
+    fn smooth() {
+        let square = mask(&["##", "##"]).contours().remove(0);
+        let smoothed = square.smooth(2);
+        assert_eq!(smoothed.points().len(), square.points().len() * 4);
+        assert!(!smoothed.is_hole());
+        for p in smoothed.points() {
+            assert!(p.x >= 0.0 && p.x <= 2.0 && p.y >= 0.0 && p.y <= 2.0);
+            assert!(!square.points().contains(p), "corner {:?} wasn't cut", p);
+        }
+        assert_eq!(square.smooth(0), square);
+    }
+}
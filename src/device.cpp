@@ -0,0 +1,20 @@
+// NiTE's `...ByDevice` initializers take a pointer to a C++ `openni::Device`.
+// Building one through its own constructor keeps the class layout and its
+// setup (device info, sensor info cache, and the playback control that file
+// devices get) in OpenNI's hands instead of mirroring them from Rust.
+
+#include <new>
+#include <OpenNI.h>
+
+extern "C" {
+
+openni::Device* nite2_device_new(OniDeviceHandle handle) {
+    // The device doesn't own the handle, so deleting it leaves the handle open
+    return new (std::nothrow) openni::Device(handle);
+}
+
+void nite2_device_delete(openni::Device* device) {
+    delete device;
+}
+
+}
@@ -0,0 +1,45 @@
+use std::os::raw::c_void;
+use std::ptr;
+use nite2_sys::OniDeviceHandle;
+use openni2::Device;
+use types::Status;
+
+// An `openni::Device` built by the C++ shim in device.cpp, for NiTE's
+// `...ByDevice` initializers. OpenNI's own constructor fills it in, including
+// the playback control it creates for `.oni` recordings.
+pub(crate) struct CppDevice(*mut c_void);
+
+extern "C" {
+    fn nite2_device_new(handle: OniDeviceHandle) -> *mut c_void;
+    fn nite2_device_delete(device: *mut c_void);
+}
+
+// `openni2::Device` keeps its handle private, but it is the struct's only
+// field, which `CppDevice::new` relies on to read it.
+const _: () = assert!(::std::mem::size_of::<Device>() == ::std::mem::size_of::<OniDeviceHandle>());
+
+impl CppDevice {
+    pub(crate) fn new(device: &Device) -> Result<CppDevice, Status> {
+        // SAFETY: `Device` is exactly one `OniDeviceHandle` (checked above),
+        // and copying the handle doesn't take ownership of the device.
+        let handle = unsafe { ptr::read(device as *const Device as *const OniDeviceHandle) };
+        let pointer = unsafe { nite2_device_new(handle) };
+        if pointer.is_null() {
+            Err(Status::Error(String::from("Couldn't allocate an openni::Device")))
+        } else {
+            Ok(CppDevice(pointer))
+        }
+    }
+
+    pub(crate) fn as_ptr(&self) -> *mut c_void {
+        self.0
+    }
+}
+
+impl Drop for CppDevice {
+    fn drop(&mut self) {
+        // The C++ device was made from a borrowed handle, so this leaves the
+        // `openni2::Device` open.
+        unsafe { nite2_device_delete(self.0) }
+    }
+}
@@ -0,0 +1,181 @@
+use nite2_sys::{NitePlane, NitePoint3f, NiteUserId};
+use openni2::{Frame, OniDepthPixel};
+use types::Status;
+use user_map::{UserMap, OwnedUserMap};
+use user_tracker::{UserTracker, UserTrackerFrame, UserData};
+
+/// The parts of a user tracker frame that don't depend on the sensor.
+pub trait TrackerFrame {
+    fn timestamp(&self) -> u64;
+    fn frame_index(&self) -> usize;
+    fn floor_confidence(&self) -> f32;
+    fn floor(&self) -> NitePlane;
+    fn user_map(&self) -> UserMap<'_>;
+    fn users(&self) -> Vec<UserData>;
+}
+
+/// Anything that produces user tracker frames, either NiTE itself
+/// (`UserTracker`) or a stand-in like `MockFrameSource`.
+/// Frames may borrow the source, as NiTE's do, so that they can't outlive it.
+pub trait FrameSource {
+    type Frame<'s>: TrackerFrame where Self: 's;
+
+    fn read_frame(&self) -> Result<Self::Frame<'_>, Status>;
+    fn track_skeleton(&self, user: NiteUserId, setting: bool) -> Result<(), Status>;
+    fn tracking_skeleton(&self, user: NiteUserId) -> bool;
+}
+
+impl<'a> TrackerFrame for UserTrackerFrame<'a> {
+    fn timestamp(&self) -> u64 {
+        UserTrackerFrame::timestamp(self)
+    }
+
+    fn frame_index(&self) -> usize {
+        UserTrackerFrame::frame_index(self)
+    }
+
+    fn floor_confidence(&self) -> f32 {
+        UserTrackerFrame::floor_confidence(self)
+    }
+
+    fn floor(&self) -> NitePlane {
+        UserTrackerFrame::floor(self)
+    }
+
+    fn user_map(&self) -> UserMap<'_> {
+        UserTrackerFrame::user_map(self)
+    }
+
+    fn users(&self) -> Vec<UserData> {
+        UserTrackerFrame::users(self)
+    }
+}
+
+impl<'a> FrameSource for UserTracker<'a> {
+    type Frame<'s> = UserTrackerFrame<'s> where Self: 's;
+
+    fn read_frame(&self) -> Result<UserTrackerFrame<'_>, Status> {
+        UserTracker::read_frame(self)
+    }
+
+    fn track_skeleton(&self, user: NiteUserId, setting: bool) -> Result<(), Status> {
+        UserTracker::track_skeleton(self, user, setting)
+    }
+
+    fn tracking_skeleton(&self, user: NiteUserId) -> bool {
+        UserTracker::tracking_skeleton(self, user)
+    }
+}
+
+/// A user tracker frame that owns all of its data.
+#[derive(Clone, Debug)]
+pub struct OwnedFrame {
+    pub timestamp: u64,
+    pub frame_index: usize,
+    pub floor: NitePlane,
+    pub floor_confidence: f32,
+    pub users: Vec<UserData>,
+    pub user_map: OwnedUserMap,
+    pub depth: Option<OwnedDepthMap>,
+}
+
+impl OwnedFrame {
+    pub fn new(frame_index: usize, timestamp: u64) -> OwnedFrame {
+        let origin = NitePoint3f { x: 0.0, y: 0.0, z: 0.0 };
+        OwnedFrame {
+            timestamp,
+            frame_index,
+            floor: NitePlane { point: origin, normal: origin },
+            floor_confidence: 0.0,
+            users: Vec::new(),
+            user_map: OwnedUserMap::new(0, 0, Vec::new()),
+            depth: None,
+        }
+    }
+
+    pub fn from_frame<F: TrackerFrame>(frame: &F) -> OwnedFrame {
+        OwnedFrame {
+            timestamp: frame.timestamp(),
+            frame_index: frame.frame_index(),
+            floor: frame.floor(),
+            floor_confidence: frame.floor_confidence(),
+            users: frame.users(),
+            user_map: frame.user_map().to_packed(),
+            depth: None,
+        }
+    }
+
+    pub fn depth_map(&self) -> Option<DepthMap<'_>> {
+        self.depth.as_ref().map(|depth| depth.as_depth_map())
+    }
+}
+
+impl TrackerFrame for OwnedFrame {
+    fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    fn frame_index(&self) -> usize {
+        self.frame_index
+    }
+
+    fn floor_confidence(&self) -> f32 {
+        self.floor_confidence
+    }
+
+    fn floor(&self) -> NitePlane {
+        self.floor
+    }
+
+    fn user_map(&self) -> UserMap<'_> {
+        self.user_map.as_user_map()
+    }
+
+    fn users(&self) -> Vec<UserData> {
+        self.users.clone()
+    }
+}
+
+/// Depth pixels, row by row with no padding.
+#[derive(Clone, Copy, Debug)]
+pub struct DepthMap<'a> {
+    pub pixels: &'a [OniDepthPixel],
+    pub width: usize,
+    pub height: usize,
+}
+
+impl<'a> DepthMap<'a> {
+    pub fn from_frame(frame: &'a Frame<OniDepthPixel>) -> DepthMap<'a> {
+        DepthMap {
+            pixels: frame.pixels(),
+            width: frame.width() as usize,
+            height: frame.height() as usize,
+        }
+    }
+
+    pub fn to_owned_map(&self) -> OwnedDepthMap {
+        OwnedDepthMap::new(self.width, self.height, self.pixels.to_vec())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct OwnedDepthMap {
+    pixels: Vec<OniDepthPixel>,
+    width: usize,
+    height: usize,
+}
+
+impl OwnedDepthMap {
+    pub fn new(width: usize, height: usize, pixels: Vec<OniDepthPixel>) -> OwnedDepthMap {
+        assert_eq!(pixels.len(), width * height, "Creating OwnedDepthMap: expected {} pixels, got {}", width * height, pixels.len());
+        OwnedDepthMap { pixels, width, height }
+    }
+
+    pub fn as_depth_map(&self) -> DepthMap<'_> {
+        DepthMap {
+            pixels: &self.pixels,
+            width: self.width,
+            height: self.height,
+        }
+    }
+}
@@ -0,0 +1,195 @@
+use std::fs::File;
+use std::io::{self, Write, BufWriter};
+use std::path::Path;
+use skeleton::LocalPose;
+use track::SkeletonTrack;
+use types::{JointType, WorldPoint};
+use util::base64_encode;
+
+const FLOAT: u32 = 5126;
+
+// NiTE works in millimeters, glTF in meters
+const SCALE: f32 = 0.001;
+
+/// Writes a track as a glTF 2.0 document with one node per `JointType`
+/// (rooted at `Torso`), a skin over those nodes, and one animation sampling
+/// every node's translation and rotation. The binary data is embedded as a
+/// base64 buffer, so the output is a single `.gltf` file.
+///
+/// Keyframes are placed at the frame timestamps, in seconds from the first
+/// tracked skeleton. Frames without a tracked skeleton get no keyframe, and
+/// are interpolated over. Keyframes are put in time order, and of several
+/// samples with the same timestamp only the last is kept, since glTF needs
+/// strictly increasing keyframe times. Axes are NiTE's world axes.
+pub fn write_gltf<W: Write>(writer: &mut W, track: &SkeletonTrack) -> io::Result<()> {
+    let rest = match track.rest_pose() {
+        Some(rest) => rest,
+        None => return Err(io::Error::new(io::ErrorKind::InvalidInput, "Writing glTF: track has no tracked skeletons")),
+    };
+    let mut keyframes: Vec<(u64, LocalPose)> = track.samples().iter()
+        .filter_map(|sample| sample.skeleton.map(|skeleton| (sample.timestamp, skeleton.local_pose())))
+        .collect();
+    // Stable, so samples sharing a timestamp stay in the order they came
+    keyframes.sort_by_key(|&(timestamp, _)| timestamp);
+    keyframes.reverse();
+    keyframes.dedup_by_key(|&mut (timestamp, _)| timestamp);
+    keyframes.reverse();
+    let start = keyframes[0].0;
+
+    let mut buffer = Buffer::new();
+
+    let bind_matrices: Vec<f32> = JointType::ALL.iter().flat_map(|&joint_type| {
+        let bind = bind_position(&rest, joint_type) * SCALE;
+        // Column major inverse of a translation
+        vec![1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, -bind.x, -bind.y, -bind.z, 1.0]
+    }).collect();
+    let bind_accessor = buffer.push(&bind_matrices, "MAT4", false);
+
+    let times: Vec<f32> = keyframes.iter()
+        .map(|&(timestamp, _)| (timestamp - start) as f32 / 1_000_000.0)
+        .collect();
+    let time_accessor = buffer.push(&times, "SCALAR", true);
+
+    let mut samplers = Vec::new();
+    let mut channels = Vec::new();
+    for &joint_type in JointType::ALL.iter() {
+        let translations: Vec<f32> = keyframes.iter().flat_map(|(_, pose)| {
+            let offset = pose.joint(joint_type).offset * SCALE;
+            vec![offset.x, offset.y, offset.z]
+        }).collect();
+        let rotations: Vec<f32> = keyframes.iter().flat_map(|(_, pose)| {
+            let rotation = pose.joint(joint_type).rotation.normalize();
+            vec![rotation.x, rotation.y, rotation.z, rotation.w]
+        }).collect();
+
+        for &(path, accessor) in [
+            ("translation", buffer.push(&translations, "VEC3", false)),
+            ("rotation", buffer.push(&rotations, "VEC4", false)),
+        ].iter() {
+            channels.push(format!(
+                r#"{{"sampler":{},"target":{{"node":{},"path":"{}"}}}}"#,
+                samplers.len(), joint_type as usize, path,
+            ));
+            samplers.push(format!(
+                r#"{{"input":{},"output":{},"interpolation":"LINEAR"}}"#,
+                time_accessor, accessor,
+            ));
+        }
+    }
+
+    let nodes: Vec<String> = JointType::ALL.iter().map(|&joint_type| {
+        let offset = rest.joint(joint_type).offset * SCALE;
+        let children: Vec<String> = joint_type.children().iter()
+            .map(|&child| (child as usize).to_string())
+            .collect();
+        let children = if children.is_empty() {
+            String::new()
+        } else {
+            format!(r#","children":[{}]"#, children.join(","))
+        };
+        format!(
+            r#"{{"name":"{:?}","translation":[{},{},{}]{}}}"#,
+            joint_type, offset.x, offset.y, offset.z, children,
+        )
+    }).collect();
+    let joints: Vec<String> = JointType::ALL.iter().map(|&joint_type| (joint_type as usize).to_string()).collect();
+    let root = JointType::Torso as usize;
+
+    write!(writer, r#"{{"asset":{{"version":"2.0","generator":"nite2"}},"scene":0,"scenes":[{{"nodes":[{}]}}],"#, root)?;
+    write!(writer, r#""nodes":[{}],"#, nodes.join(","))?;
+    write!(writer, r#""skins":[{{"name":"User {}","joints":[{}],"skeleton":{},"inverseBindMatrices":{}}}],"#,
+        track.user_id(), joints.join(","), root, bind_accessor)?;
+    write!(writer, r#""animations":[{{"name":"User {}","samplers":[{}],"channels":[{}]}}],"#,
+        track.user_id(), samplers.join(","), channels.join(","))?;
+    write!(writer, r#""accessors":[{}],"bufferViews":[{}],"#, buffer.accessors.join(","), buffer.views.join(","))?;
+    writeln!(writer, r#""buffers":[{{"byteLength":{},"uri":"data:application/octet-stream;base64,{}"}}]}}"#,
+        buffer.data.len(), base64_encode(&buffer.data))
+}
+
+pub fn save_gltf<P: AsRef<Path>>(path: P, track: &SkeletonTrack) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    write_gltf(&mut writer, track)?;
+    writer.flush()
+}
+
+// Position of a joint in the rest pose, from the offsets along its chain.
+fn bind_position(rest: &LocalPose, joint_type: JointType) -> WorldPoint {
+    let offset = rest.joint(joint_type).offset;
+    match joint_type.parent() {
+        Some(parent) => bind_position(rest, parent) + offset,
+        None => offset,
+    }
+}
+
+// A single glTF buffer with one view and accessor per array of floats.
+struct Buffer {
+    data: Vec<u8>,
+    views: Vec<String>,
+    accessors: Vec<String>,
+}
+
+impl Buffer {
+    fn new() -> Buffer {
+        Buffer {
+            data: Vec::new(),
+            views: Vec::new(),
+            accessors: Vec::new(),
+        }
+    }
+
+    fn push(&mut self, values: &[f32], accessor_type: &str, bounds: bool) -> usize {
+        let components = match accessor_type {
+            "SCALAR" => 1,
+            "VEC3" => 3,
+            "VEC4" => 4,
+            "MAT4" => 16,
+            _ => unreachable!(),
+        };
+        self.views.push(format!(
+            r#"{{"buffer":0,"byteOffset":{},"byteLength":{}}}"#,
+            self.data.len(), values.len() * 4,
+        ));
+        for value in values {
+            self.data.extend_from_slice(&value.to_le_bytes());
+        }
+
+        // Animation inputs have to declare their range
+        let bounds = if bounds {
+            let min = values.iter().cloned().fold(f32::INFINITY, f32::min);
+            let max = values.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+            format!(r#","min":[{}],"max":[{}]"#, min, max)
+        } else {
+            String::new()
+        };
+        self.accessors.push(format!(
+            r#"{{"bufferView":{},"componentType":{},"count":{},"type":"{}"{}}}"#,
+            self.views.len() - 1, FLOAT, values.len() / components, accessor_type, bounds,
+        ));
+        self.accessors.len() - 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mock::MockUser;
+    use types::SkeletonState;
+    use user_tracker::UserData;
+
+    #[test]
+    fn keyframes_are_ordered_and_unique() {
+        let user = UserData::from(MockUser::new(1)
+            .skeleton_state(SkeletonState::Tracked)
+            .joint(JointType::Neck, WorldPoint::new(0.0, 300.0, 0.0), 1.0));
+        let skeleton = user.skeleton().ok();
+        let mut track = SkeletonTrack::new(1);
+        for &timestamp in [66_666, 0, 33_333, 33_333].iter() {
+            track.push(timestamp, 0, skeleton);
+        }
+
+        let mut gltf = Vec::new();
+        write_gltf(&mut gltf, &track).expect("track has skeletons");
+        let gltf = String::from_utf8(gltf).expect("glTF is JSON");
+        assert!(gltf.contains(r#""count":3,"type":"SCALAR","min":[0],"max":[0.066666]"#), "{}", gltf);
+    }
+}
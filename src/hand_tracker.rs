@@ -0,0 +1,317 @@
+use std::os::raw::{c_ulonglong, c_int, c_void};
+use std::marker::PhantomData;
+use std::{ptr, slice};
+use device::CppDevice;
+use types::{Status, GestureType, WorldPoint};
+use openni2::{
+    Device,
+    Frame,
+    OniDepthPixel,
+    frame_from_pointer as oni_frame_from_pointer,
+};
+use nite2_sys::*;
+
+const NITE_HAND_STATE_LOST: c_int = 0;
+const NITE_HAND_STATE_NEW: c_int = 1;
+const NITE_HAND_STATE_TRACKED: c_int = 2;
+const NITE_HAND_STATE_TOUCHING_FOV: c_int = 4;
+
+const NITE_GESTURE_STATE_NEW: c_int = 1;
+const NITE_GESTURE_STATE_IN_PROGRESS: c_int = 2;
+const NITE_GESTURE_STATE_COMPLETED: c_int = 4;
+
+pub struct HandTracker<'a> {
+    handle: NiteHandTrackerHandle,
+    _device: Option<CppDevice>,
+    _device_lifetime: PhantomData<&'a Device>,
+}
+
+impl<'a> HandTracker<'a> {
+    pub fn open_default() -> Result<HandTracker<'a>, Status> {
+        let mut handle: NiteHandTrackerHandle = ptr::null_mut();
+        let status = unsafe {
+            niteInitializeHandTracker(&mut handle)
+        }.into();
+        match status {
+            Status::Ok => Ok(HandTracker {
+                handle,
+                _device: None,
+                _device_lifetime: PhantomData,
+            }),
+            _ => Err(status),
+        }
+    }
+
+    /// Tracks hands on an already opened device, which may be a live sensor
+    /// or an `.oni` recording.
+    pub fn open(device: &'a Device) -> Result<HandTracker<'a>, Status> {
+        let mut handle: NiteHandTrackerHandle = ptr::null_mut();
+        let cpp_device = CppDevice::new(device)?;
+        let status = unsafe {
+            niteInitializeHandTrackerByDevice(cpp_device.as_ptr(), &mut handle)
+        }.into();
+        match status {
+            Status::Ok => Ok(HandTracker {
+                handle,
+                _device: Some(cpp_device),
+                _device_lifetime: PhantomData,
+            }),
+            _ => Err(status),
+        }
+    }
+
+    pub fn read_frame(&self) -> Result<HandTrackerFrame<'_>, Status> {
+        let mut pointer = ptr::null_mut();
+        let status = unsafe {
+            niteReadHandTrackerFrame(self.handle, &mut pointer)
+        }.into();
+        match status {
+            Status::Ok => Ok(frame_from_pointer(pointer, self.handle)),
+            _ => Err(status),
+        }
+    }
+
+    /// Starts tracking a hand at `position`, which is usually taken from
+    /// a completed gesture. Returns the id NiTE assigned to the new hand.
+    pub fn start_hand_tracking(&self, position: WorldPoint) -> Result<NiteHandId, Status> {
+        let point = NitePoint3f { x: position.x, y: position.y, z: position.z };
+        let mut hand_id: NiteHandId = 0;
+        let status = unsafe {
+            niteStartHandTracking(self.handle, &point, &mut hand_id)
+        }.into();
+        match status {
+            Status::Ok => Ok(hand_id),
+            _ => Err(status),
+        }
+    }
+
+    pub fn stop_hand_tracking(&self, hand: NiteHandId) {
+        unsafe { niteStopHandTracking(self.handle, hand); }
+    }
+
+    pub fn stop_all_hand_tracking(&self) {
+        unsafe { niteStopAllHandTracking(self.handle); }
+    }
+
+    /// Sets the hand smoothing factor, from 0.0 (no smoothing) up to, but
+    /// not including, 1.0 (hands never move).
+    pub fn set_smoothing_factor(&self, factor: f32) -> Result<(), Status> {
+        if !(0.0..1.0).contains(&factor) {
+            return Err(Status::Error(format!("Hand smoothing factor {} is outside of [0.0, 1.0)", factor)));
+        }
+        let status = unsafe { niteSetHandSmoothingFactor(self.handle, factor) }.into();
+        if let Status::Ok = status {
+            Ok(())
+        } else {
+            Err(status)
+        }
+    }
+
+    pub fn smoothing_factor(&self) -> Result<f32, Status> {
+        let mut factor = 0.0;
+        let status = unsafe { niteGetHandSmoothingFactor(self.handle, &mut factor) }.into();
+        match status {
+            Status::Ok => Ok(factor),
+            _ => Err(status),
+        }
+    }
+
+    pub fn start_gesture_detection(&self, gesture: GestureType) -> Result<(), Status> {
+        let status = unsafe {
+            niteStartGestureDetection(self.handle, gesture as NiteGestureType)
+        }.into();
+        if let Status::Ok = status {
+            Ok(())
+        } else {
+            Err(status)
+        }
+    }
+
+    pub fn stop_gesture_detection(&self, gesture: GestureType) {
+        unsafe { niteStopGestureDetection(self.handle, gesture as NiteGestureType); }
+    }
+
+    pub fn stop_all_gesture_detection(&self) {
+        unsafe { niteStopAllGestureDetection(self.handle); }
+    }
+
+    pub fn register_next_frame_callback<'s, F: FnMut(&HandTracker) + 's>(&'s self, mut callback: F) -> Result<HandTrackerListener<'s>, Status> {
+        extern "C" fn callback_wrapper(cookie: *mut c_void) {
+            let closure: &mut dyn FnMut() = unsafe { &mut *(cookie as *mut Box<dyn FnMut()>) };
+            closure();
+        }
+
+        // NiTE keeps both pointers until the callbacks are unregistered, so
+        // they're boxed and owned by the listener, which frees them after that.
+        let closure: Box<Box<dyn FnMut() + 's>> = Box::new(Box::new(move || {
+            callback(self);
+        }));
+        let closure = Box::into_raw(closure);
+        let mut callback_struct = Box::new(NiteHandTrackerCallbacks {
+            readyForNextFrame: Some(callback_wrapper),
+        });
+        let status = unsafe {
+            niteRegisterHandTrackerCallbacks(
+                self.handle,
+                &mut *callback_struct,
+                closure as *mut _,
+            )
+        }.into();
+        if let Status::Ok = status {
+            Ok(HandTrackerListener {
+                hand_tracker_handle: &self.handle,
+                callback_struct,
+                closure,
+            })
+        } else {
+            drop(unsafe { Box::from_raw(closure) });
+            Err(status)
+        }
+    }
+}
+
+impl<'a> Drop for HandTracker<'a> {
+    fn drop(&mut self) {
+        unsafe { niteShutdownHandTracker(self.handle); }
+    }
+}
+
+#[derive(Debug)]
+pub struct HandTrackerFrame<'a> {
+    nite_frame: &'a NiteHandTrackerFrame,
+    frame_pointer: *mut NiteHandTrackerFrame,
+    hand_tracker_handle: NiteHandTrackerHandle,
+}
+
+fn frame_from_pointer<'a>(frame_pointer: *mut NiteHandTrackerFrame, handle: NiteHandTrackerHandle) -> HandTrackerFrame<'a> {
+    assert!(!frame_pointer.is_null(), "Creating HandTrackerFrame: *mut NiteHandTrackerFrame is null");
+    let nite_frame: &NiteHandTrackerFrame = unsafe { &*frame_pointer };
+    HandTrackerFrame {
+        nite_frame,
+        frame_pointer,
+        hand_tracker_handle: handle,
+    }
+}
+
+impl<'a> HandTrackerFrame<'a> {
+    pub fn timestamp(&self) -> c_ulonglong {
+        self.nite_frame.timestamp
+    }
+
+    pub fn frame_index(&self) -> usize {
+        self.nite_frame.frameIndex as usize
+    }
+
+    pub fn depth_frame(&self) -> Frame<OniDepthPixel> {
+        unsafe { oni_frame_from_pointer(self.nite_frame.pDepthFrame) }
+    }
+
+    pub fn hands(&self) -> Vec<HandData> {
+        if self.nite_frame.handCount == 0 {
+            vec![]
+        } else {
+            assert!(!self.nite_frame.pHands.is_null(), "Creating NiteHandData slice: NiteHandTrackerFrame.pHands is null");
+            unsafe { slice::from_raw_parts(self.nite_frame.pHands, self.hand_count()) }.iter().map(|&hand_data| {
+                HandData(hand_data)
+            }).collect()
+        }
+    }
+
+    pub fn gestures(&self) -> Vec<GestureData> {
+        if self.nite_frame.gestureCount == 0 {
+            vec![]
+        } else {
+            assert!(!self.nite_frame.pGestures.is_null(), "Creating NiteGestureData slice: NiteHandTrackerFrame.pGestures is null");
+            unsafe { slice::from_raw_parts(self.nite_frame.pGestures, self.gesture_count()) }.iter().map(|&gesture_data| {
+                GestureData(gesture_data)
+            }).collect()
+        }
+    }
+
+    #[inline]
+    pub fn hand_count(&self) -> usize {
+        self.nite_frame.handCount as usize
+    }
+
+    #[inline]
+    pub fn gesture_count(&self) -> usize {
+        self.nite_frame.gestureCount as usize
+    }
+}
+
+impl<'a> Drop for HandTrackerFrame<'a> {
+    fn drop(&mut self) {
+        unsafe {
+            niteHandTrackerFrameRelease(self.hand_tracker_handle, self.frame_pointer)
+        };
+    }
+}
+
+pub struct HandTrackerListener<'tracker> {
+    hand_tracker_handle: &'tracker NiteHandTrackerHandle,
+    callback_struct: Box<NiteHandTrackerCallbacks>,
+    closure: *mut Box<dyn FnMut() + 'tracker>,
+}
+
+impl<'tracker> Drop for HandTrackerListener<'tracker> {
+    fn drop(&mut self) {
+        unsafe {
+            niteUnregisterHandTrackerCallbacks(*self.hand_tracker_handle, &mut *self.callback_struct);
+            drop(Box::from_raw(self.closure));
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct HandData(NiteHandData);
+
+impl HandData {
+    pub fn id(&self) -> NiteHandId {
+        self.0.id
+    }
+
+    pub fn position(&self) -> WorldPoint {
+        self.0.position.into()
+    }
+
+    pub fn is_new(&self) -> bool {
+        self.0.state & NITE_HAND_STATE_NEW != 0
+    }
+
+    pub fn is_tracking(&self) -> bool {
+        self.0.state & NITE_HAND_STATE_TRACKED != 0
+    }
+
+    pub fn is_lost(&self) -> bool {
+        self.0.state == NITE_HAND_STATE_LOST
+    }
+
+    pub fn is_touching_fov(&self) -> bool {
+        self.0.state & NITE_HAND_STATE_TOUCHING_FOV != 0
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct GestureData(NiteGestureData);
+
+impl GestureData {
+    pub fn gesture_type(&self) -> GestureType {
+        self.0.type_.into()
+    }
+
+    pub fn current_position(&self) -> WorldPoint {
+        self.0.currentPosition.into()
+    }
+
+    pub fn is_new(&self) -> bool {
+        self.0.state & NITE_GESTURE_STATE_NEW != 0
+    }
+
+    pub fn is_in_progress(&self) -> bool {
+        self.0.state & NITE_GESTURE_STATE_IN_PROGRESS != 0
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.0.state & NITE_GESTURE_STATE_COMPLETED != 0
+    }
+}
@@ -0,0 +1,366 @@
+// Conversions to and from the math crates behind the `mint`, `glam` and
+// `nalgebra` features. Planes convert to and from a 4-vector (a, b, c, d)
+// with a unit normal (a, b, c), for the plane ax + by + cz + d = 0.
+//
+// Only this crate's own types get conversions. The orphan rule rules out
+// impls between the `Nite*` FFI structs from nite2-sys and the foreign math
+// types, so convert those through `WorldPoint`, `Quaternion` or `Plane`.
+use quaternion::Quaternion;
+use skeleton::Skeleton;
+use types::{JointType, WorldPoint, DepthPoint, Plane};
+
+fn joint_positions<T: From<WorldPoint>>(skeleton: &Skeleton) -> [T; JointType::ALL.len()] {
+    let joints = skeleton.joints();
+    ::std::array::from_fn(|i| joints[i].position().into())
+}
+
+fn joint_orientations<T: From<Quaternion>>(skeleton: &Skeleton) -> [T; JointType::ALL.len()] {
+    let joints = skeleton.joints();
+    ::std::array::from_fn(|i| joints[i].orientation().into())
+}
+
+fn plane_coefficients(plane: &Plane) -> [f32; 4] {
+    let normal = plane.normal.normalize();
+    [normal.x, normal.y, normal.z, -normal.dot(plane.point)]
+}
+
+fn plane_from_coefficients(a: f32, b: f32, c: f32, d: f32) -> Plane {
+    let normal = WorldPoint::new(a, b, c);
+    let length_squared = normal.dot(normal);
+    let point = if length_squared == 0.0 {
+        WorldPoint::new(0.0, 0.0, 0.0)
+    } else {
+        normal * (-d / length_squared)
+    };
+    Plane { point, normal: normal.normalize() }
+}
+
+#[cfg(feature = "mint")]
+mod mint_conversions {
+    use mint;
+    use super::*;
+
+    macro_rules! impl_mint_point {
+        ($point:ident) => {
+            impl From<$point> for mint::Point3<f32> {
+                fn from(p: $point) -> mint::Point3<f32> {
+                    mint::Point3 { x: p.x, y: p.y, z: p.z }
+                }
+            }
+
+            impl From<mint::Point3<f32>> for $point {
+                fn from(p: mint::Point3<f32>) -> $point {
+                    $point { x: p.x, y: p.y, z: p.z }
+                }
+            }
+
+            impl From<$point> for mint::Vector3<f32> {
+                fn from(p: $point) -> mint::Vector3<f32> {
+                    mint::Vector3 { x: p.x, y: p.y, z: p.z }
+                }
+            }
+
+            impl From<mint::Vector3<f32>> for $point {
+                fn from(v: mint::Vector3<f32>) -> $point {
+                    $point { x: v.x, y: v.y, z: v.z }
+                }
+            }
+        }
+    }
+
+    impl_mint_point!(WorldPoint);
+    impl_mint_point!(DepthPoint);
+
+    impl From<Quaternion> for mint::Quaternion<f32> {
+        fn from(q: Quaternion) -> mint::Quaternion<f32> {
+            mint::Quaternion { v: mint::Vector3 { x: q.x, y: q.y, z: q.z }, s: q.w }
+        }
+    }
+
+    impl From<mint::Quaternion<f32>> for Quaternion {
+        fn from(q: mint::Quaternion<f32>) -> Quaternion {
+            Quaternion { x: q.v.x, y: q.v.y, z: q.v.z, w: q.s }
+        }
+    }
+
+    impl From<Plane> for mint::Vector4<f32> {
+        fn from(plane: Plane) -> mint::Vector4<f32> {
+            plane_coefficients(&plane).into()
+        }
+    }
+
+    impl From<mint::Vector4<f32>> for Plane {
+        fn from(v: mint::Vector4<f32>) -> Plane {
+            plane_from_coefficients(v.x, v.y, v.z, v.w)
+        }
+    }
+
+    impl<'a> From<&'a Skeleton> for [mint::Point3<f32>; JointType::ALL.len()] {
+        fn from(skeleton: &'a Skeleton) -> [mint::Point3<f32>; JointType::ALL.len()] {
+            joint_positions(skeleton)
+        }
+    }
+
+    impl<'a> From<&'a Skeleton> for [mint::Vector3<f32>; JointType::ALL.len()] {
+        fn from(skeleton: &'a Skeleton) -> [mint::Vector3<f32>; JointType::ALL.len()] {
+            joint_positions(skeleton)
+        }
+    }
+
+    impl<'a> From<&'a Skeleton> for [mint::Quaternion<f32>; JointType::ALL.len()] {
+        fn from(skeleton: &'a Skeleton) -> [mint::Quaternion<f32>; JointType::ALL.len()] {
+            joint_orientations(skeleton)
+        }
+    }
+}
+
+#[cfg(feature = "glam")]
+mod glam_conversions {
+    use glam::{Vec3, Vec4, Quat};
+    use super::*;
+
+    macro_rules! impl_glam_point {
+        ($point:ident) => {
+            impl From<$point> for Vec3 {
+                fn from(p: $point) -> Vec3 {
+                    Vec3::new(p.x, p.y, p.z)
+                }
+            }
+
+            impl From<Vec3> for $point {
+                fn from(v: Vec3) -> $point {
+                    $point { x: v.x, y: v.y, z: v.z }
+                }
+            }
+        }
+    }
+
+    impl_glam_point!(WorldPoint);
+    impl_glam_point!(DepthPoint);
+
+    impl From<Quaternion> for Quat {
+        fn from(q: Quaternion) -> Quat {
+            Quat::from_xyzw(q.x, q.y, q.z, q.w)
+        }
+    }
+
+    impl From<Quat> for Quaternion {
+        fn from(q: Quat) -> Quaternion {
+            Quaternion { x: q.x, y: q.y, z: q.z, w: q.w }
+        }
+    }
+
+    impl From<Plane> for Vec4 {
+        fn from(plane: Plane) -> Vec4 {
+            Vec4::from_array(plane_coefficients(&plane))
+        }
+    }
+
+    impl From<Vec4> for Plane {
+        fn from(v: Vec4) -> Plane {
+            plane_from_coefficients(v.x, v.y, v.z, v.w)
+        }
+    }
+
+    impl<'a> From<&'a Skeleton> for [Vec3; JointType::ALL.len()] {
+        fn from(skeleton: &'a Skeleton) -> [Vec3; JointType::ALL.len()] {
+            joint_positions(skeleton)
+        }
+    }
+
+    impl<'a> From<&'a Skeleton> for [Quat; JointType::ALL.len()] {
+        fn from(skeleton: &'a Skeleton) -> [Quat; JointType::ALL.len()] {
+            joint_orientations(skeleton)
+        }
+    }
+}
+
+#[cfg(feature = "nalgebra")]
+mod nalgebra_conversions {
+    use nalgebra::{Point3, Vector3, Vector4, Quaternion as NaQuaternion, UnitQuaternion};
+    use super::*;
+
+    macro_rules! impl_nalgebra_point {
+        ($point:ident) => {
+            impl From<$point> for Point3<f32> {
+                fn from(p: $point) -> Point3<f32> {
+                    Point3::new(p.x, p.y, p.z)
+                }
+            }
+
+            impl From<Point3<f32>> for $point {
+                fn from(p: Point3<f32>) -> $point {
+                    $point { x: p.x, y: p.y, z: p.z }
+                }
+            }
+
+            impl From<$point> for Vector3<f32> {
+                fn from(p: $point) -> Vector3<f32> {
+                    Vector3::new(p.x, p.y, p.z)
+                }
+            }
+
+            impl From<Vector3<f32>> for $point {
+                fn from(v: Vector3<f32>) -> $point {
+                    $point { x: v.x, y: v.y, z: v.z }
+                }
+            }
+        }
+    }
+
+    impl_nalgebra_point!(WorldPoint);
+    impl_nalgebra_point!(DepthPoint);
+
+    impl From<Quaternion> for NaQuaternion<f32> {
+        fn from(q: Quaternion) -> NaQuaternion<f32> {
+            NaQuaternion::new(q.w, q.x, q.y, q.z)
+        }
+    }
+
+    impl From<NaQuaternion<f32>> for Quaternion {
+        fn from(q: NaQuaternion<f32>) -> Quaternion {
+            Quaternion { x: q.i, y: q.j, z: q.k, w: q.w }
+        }
+    }
+
+    /// NiTE's all-zero orientations become the identity.
+    impl From<Quaternion> for UnitQuaternion<f32> {
+        fn from(q: Quaternion) -> UnitQuaternion<f32> {
+            UnitQuaternion::new_unchecked(q.normalize().into())
+        }
+    }
+
+    impl From<UnitQuaternion<f32>> for Quaternion {
+        fn from(q: UnitQuaternion<f32>) -> Quaternion {
+            q.into_inner().into()
+        }
+    }
+
+    impl From<Plane> for Vector4<f32> {
+        fn from(plane: Plane) -> Vector4<f32> {
+            Vector4::from(plane_coefficients(&plane))
+        }
+    }
+
+    impl From<Vector4<f32>> for Plane {
+        fn from(v: Vector4<f32>) -> Plane {
+            plane_from_coefficients(v.x, v.y, v.z, v.w)
+        }
+    }
+
+    impl<'a> From<&'a Skeleton> for [Point3<f32>; JointType::ALL.len()] {
+        fn from(skeleton: &'a Skeleton) -> [Point3<f32>; JointType::ALL.len()] {
+            joint_positions(skeleton)
+        }
+    }
+
+    impl<'a> From<&'a Skeleton> for [Vector3<f32>; JointType::ALL.len()] {
+        fn from(skeleton: &'a Skeleton) -> [Vector3<f32>; JointType::ALL.len()] {
+            joint_positions(skeleton)
+        }
+    }
+
+    impl<'a> From<&'a Skeleton> for [UnitQuaternion<f32>; JointType::ALL.len()] {
+        fn from(skeleton: &'a Skeleton) -> [UnitQuaternion<f32>; JointType::ALL.len()] {
+            joint_orientations(skeleton)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mock::MockUser;
+    use types::SkeletonState;
+    use user_tracker::UserData;
+
+    const POINT: WorldPoint = WorldPoint { x: 1.0, y: -2.0, z: 3.0 };
+    const ROTATION: Quaternion = Quaternion { x: 0.0, y: 0.6, z: 0.0, w: 0.8 };
+
+    // The floor 900mm down: y - -900 = 0, or 0x + 1y + 0z + 900 = 0
+    fn floor() -> Plane {
+        Plane {
+            point: WorldPoint::new(0.0, -900.0, 0.0),
+            normal: WorldPoint::new(0.0, 1.0, 0.0),
+        }
+    }
+
+    fn user() -> UserData {
+        MockUser::new(1)
+            .skeleton_state(SkeletonState::Tracked)
+            .joint(JointType::LeftHand, POINT, 1.0)
+            .joint_orientation(JointType::LeftHand, ROTATION.into())
+            .into()
+    }
+
+    #[cfg(feature = "mint")]
+    #[test]
+    fn mint_round_trip() {
+        use mint;
+        let point: mint::Point3<f32> = POINT.into();
+        assert_eq!(WorldPoint::from(point), POINT);
+        let vector: mint::Vector3<f32> = DepthPoint::new(1.0, 2.0, 3.0).into();
+        assert_eq!(DepthPoint::from(vector), DepthPoint::new(1.0, 2.0, 3.0));
+        let rotation: mint::Quaternion<f32> = ROTATION.into();
+        assert_eq!((rotation.v.y, rotation.s), (0.6, 0.8));
+        assert_eq!(Quaternion::from(rotation), ROTATION);
+        let plane: mint::Vector4<f32> = floor().into();
+        assert_eq!(plane, mint::Vector4 { x: 0.0, y: 1.0, z: 0.0, w: 900.0 });
+        assert_eq!(Plane::from(plane), floor());
+
+        let user = user();
+        let skeleton = user.skeleton().expect("tracked skeleton");
+        let positions: [mint::Point3<f32>; 15] = (&skeleton).into();
+        assert_eq!(WorldPoint::from(positions[JointType::LeftHand as usize]), POINT);
+        let orientations: [mint::Quaternion<f32>; 15] = (&skeleton).into();
+        assert_eq!(Quaternion::from(orientations[JointType::LeftHand as usize]), ROTATION);
+    }
+
+    #[cfg(feature = "glam")]
+    #[test]
+    fn glam_round_trip() {
+        use glam::{Vec3, Vec4, Quat};
+        let point: Vec3 = POINT.into();
+        assert_eq!(point, Vec3::new(1.0, -2.0, 3.0));
+        assert_eq!(WorldPoint::from(point), POINT);
+        let rotation: Quat = ROTATION.into();
+        assert_eq!(rotation, Quat::from_xyzw(0.0, 0.6, 0.0, 0.8));
+        assert_eq!(Quaternion::from(rotation), ROTATION);
+        let plane: Vec4 = floor().into();
+        assert_eq!(plane, Vec4::new(0.0, 1.0, 0.0, 900.0));
+        assert_eq!(Plane::from(plane), floor());
+
+        let user = user();
+        let skeleton = user.skeleton().expect("tracked skeleton");
+        let positions: [Vec3; 15] = (&skeleton).into();
+        assert_eq!(positions[JointType::LeftHand as usize], point);
+        let orientations: [Quat; 15] = (&skeleton).into();
+        assert_eq!(orientations[JointType::LeftHand as usize], rotation);
+    }
+
+    #[cfg(feature = "nalgebra")]
+    #[test]
+    fn nalgebra_round_trip() {
+        use nalgebra::{Point3, Vector3, Vector4, Quaternion as NaQuaternion, UnitQuaternion};
+        let point: Point3<f32> = POINT.into();
+        assert_eq!(point, Point3::new(1.0, -2.0, 3.0));
+        assert_eq!(WorldPoint::from(point), POINT);
+        let vector: Vector3<f32> = POINT.into();
+        assert_eq!(WorldPoint::from(vector), POINT);
+        let rotation: NaQuaternion<f32> = ROTATION.into();
+        assert_eq!(rotation, NaQuaternion::new(0.8, 0.0, 0.6, 0.0));
+        assert_eq!(Quaternion::from(rotation), ROTATION);
+        let unit: UnitQuaternion<f32> = Quaternion::new(0.0, 0.0, 0.0, 0.0).into();
+        assert_eq!(Quaternion::from(unit), Quaternion::identity());
+        let plane: Vector4<f32> = floor().into();
+        assert_eq!(plane, Vector4::new(0.0, 1.0, 0.0, 900.0));
+        assert_eq!(Plane::from(plane), floor());
+
+        let user = user();
+        let skeleton = user.skeleton().expect("tracked skeleton");
+        let positions: [Point3<f32>; 15] = (&skeleton).into();
+        assert_eq!(positions[JointType::LeftHand as usize], point);
+        let orientations: [UnitQuaternion<f32>; 15] = (&skeleton).into();
+        assert_eq!(Quaternion::from(orientations[JointType::LeftHand as usize]), ROTATION);
+    }
+}
@@ -1,10 +1,41 @@
 extern crate nite2_sys;
 extern crate openni2;
+#[cfg(feature = "serde")]
+#[macro_use]
+extern crate serde;
+#[cfg(feature = "mint")]
+extern crate mint;
+#[cfg(feature = "glam")]
+extern crate glam;
+#[cfg(feature = "nalgebra")]
+extern crate nalgebra;
+#[cfg(all(test, feature = "serde"))]
+extern crate serde_json;
 
 mod types;
 mod user_tracker;
+mod user_map;
+mod mask;
+mod contour;
 mod user_tracker_manager;
+mod hand_tracker;
 mod skeleton;
+mod quaternion;
+mod device;
+mod frame_source;
+mod mock;
+mod recording;
+mod snapshot;
+mod track;
+mod bvh;
+mod gltf;
+mod trajectory;
+mod c3d;
+mod mcap;
+mod svg;
+mod util;
+#[cfg(any(feature = "mint", feature = "glam", feature = "nalgebra"))]
+mod interop;
 
 use nite2_sys::*;
 pub use nite2_sys::{
@@ -14,26 +45,123 @@ pub use nite2_sys::{
     NitePoint3f,
     NiteQuaternion,
     NiteUserId,
+    NiteHandId,
 };
 
 pub use types::{
     Status,
     JointType,
     SkeletonState,
+    UserState,
     PoseType,
     GestureType,
 
     DepthPoint,
     WorldPoint,
+    Plane,
 };
 
+pub use skeleton::{
+    Skeleton,
+    Joint,
+    Bone,
+    Limb,
+    LocalJoint,
+    LocalPose,
+};
+
+pub use quaternion::Quaternion;
+
 pub use user_tracker::{
     UserTracker,
     UserTrackerFrame,
+    UserData,
+    PoseData,
+};
+
+pub use user_map::{
     UserMap,
+    OwnedUserMap,
+};
+
+pub use mask::{
+    Mask,
+    PixelBounds,
+    UserRegion,
+};
+
+pub use contour::Contour;
+
+pub use frame_source::{
+    FrameSource,
+    TrackerFrame,
+    OwnedFrame,
+    DepthMap,
+    OwnedDepthMap,
+};
+
+pub use mock::{
+    MockFrameSource,
+    MockUser,
+};
+
+pub use recording::{
+    Recorder,
+    Player,
+    PlaybackSpeed,
+};
+
+pub use snapshot::{
+    JointSnapshot,
+    SkeletonSnapshot,
+    PoseSnapshot,
+    UserSnapshot,
+    FrameSnapshot,
 };
 
-pub use user_tracker_manager::UserTrackerManager;
+pub use user_tracker_manager::{
+    UserTrackerManager,
+    UserEvent,
+    UserRecord,
+};
+
+pub use hand_tracker::{
+    HandTracker,
+    HandTrackerFrame,
+    HandTrackerListener,
+    HandData,
+    GestureData,
+};
+
+pub use track::{
+    SkeletonTrack,
+    TrackSample,
+};
+
+pub use bvh::{
+    write_bvh,
+    save_bvh,
+};
+
+pub use gltf::{
+    write_gltf,
+    save_gltf,
+};
+
+pub use trajectory::{
+    CsvWriter,
+    write_trc,
+    save_trc,
+};
+
+pub use c3d::{
+    write_c3d,
+    save_c3d,
+};
+
+pub use mcap::McapWriter;
+
+pub use svg::SvgRenderer;
 
 pub fn init() -> Result<(), Status> {
     let status = unsafe { niteInitialize() }.into();
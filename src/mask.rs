@@ -0,0 +1,399 @@
+use nite2_sys::NiteUserId;
+use user_map::OwnedUserMap;
+
+/// A binary image with the dimensions of a user map, such as the pixels
+/// belonging to one user.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Mask {
+    pixels: Vec<bool>,
+    width: usize,
+    height: usize,
+}
+
+impl Mask {
+    pub fn new(width: usize, height: usize) -> Mask {
+        Mask {
+            pixels: vec![false; width * height],
+            width,
+            height,
+        }
+    }
+
+    pub fn from_pixels(width: usize, height: usize, pixels: Vec<bool>) -> Mask {
+        assert_eq!(pixels.len(), width * height, "Creating Mask: expected {} pixels, got {}", width * height, pixels.len());
+        Mask { pixels, width, height }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Pixels outside the mask are unset.
+    pub fn get(&self, x: usize, y: usize) -> bool {
+        x < self.width && y < self.height && self.pixels[y * self.width + x]
+    }
+
+    /// Panics if the pixel is outside the mask.
+    pub fn set(&mut self, x: usize, y: usize, value: bool) {
+        assert!(x < self.width && y < self.height, "Mask pixel ({}, {}) out of bounds ({}x{})", x, y, self.width, self.height);
+        self.pixels[y * self.width + x] = value;
+    }
+
+    /// Row by row, with no padding.
+    pub fn pixels(&self) -> &[bool] {
+        &self.pixels
+    }
+
+    pub fn count(&self) -> usize {
+        self.pixels.iter().filter(|&&px| px).count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        !self.pixels.contains(&true)
+    }
+
+    /// Unsets pixels with an unset pixel within `radius` (a square of
+    /// `2 * radius + 1` pixels). Pixels past the edges count as unset, so
+    /// regions touching the edge are worn away from it too.
+    pub fn erode(&self, radius: usize) -> Mask {
+        self.square_filter(radius, true)
+    }
+
+    /// Sets pixels with a set pixel within `radius`.
+    pub fn dilate(&self, radius: usize) -> Mask {
+        self.square_filter(radius, false)
+    }
+
+    /// Erodes then dilates, removing specks and thin spurs.
+    pub fn open(&self, radius: usize) -> Mask {
+        self.erode(radius).dilate(radius)
+    }
+
+    /// Dilates then erodes, filling pinholes and narrow gaps. Regions near
+    /// the edges are closed as if the mask carried on past them, so they
+    /// neither grow out to the edge nor get worn away from it.
+    pub fn close(&self, radius: usize) -> Mask {
+        self.padded(radius).dilate(radius).erode(radius).cropped(radius)
+    }
+
+    /// Unsets every 4-connected region with fewer than `min_pixels` pixels.
+    pub fn remove_small_blobs(&self, min_pixels: usize) -> Mask {
+        let mut result = self.clone();
+        let mut visited = vec![false; self.pixels.len()];
+        let mut stack = Vec::new();
+        let mut blob = Vec::new();
+        for start in 0..self.pixels.len() {
+            if !self.pixels[start] || visited[start] {
+                continue;
+            }
+            visited[start] = true;
+            stack.push(start);
+            blob.clear();
+            while let Some(i) = stack.pop() {
+                blob.push(i);
+                let (x, y) = (i % self.width, i / self.width);
+                let neighbors = [
+                    (x > 0, i.wrapping_sub(1)),
+                    (x + 1 < self.width, i + 1),
+                    (y > 0, i.wrapping_sub(self.width)),
+                    (y + 1 < self.height, i + self.width),
+                ];
+                for &(in_bounds, neighbor) in neighbors.iter() {
+                    if in_bounds && self.pixels[neighbor] && !visited[neighbor] {
+                        visited[neighbor] = true;
+                        stack.push(neighbor);
+                    }
+                }
+            }
+            if blob.len() < min_pixels {
+                for &i in &blob {
+                    result.pixels[i] = false;
+                }
+            }
+        }
+        result
+    }
+
+    // A copy with `border` unset pixels added around each side.
+    fn padded(&self, border: usize) -> Mask {
+        let width = self.width + 2 * border;
+        let mut padded = Mask::new(width, self.height + 2 * border);
+        for (y, row) in self.pixels.chunks(self.width.max(1)).enumerate() {
+            let start = (y + border) * width + border;
+            padded.pixels[start..start + self.width].copy_from_slice(row);
+        }
+        padded
+    }
+
+    // The inside of a mask made by `padded`.
+    fn cropped(&self, border: usize) -> Mask {
+        let width = self.width - 2 * border;
+        let height = self.height - 2 * border;
+        let mut pixels = Vec::with_capacity(width * height);
+        for y in border..border + height {
+            let start = y * self.width + border;
+            pixels.extend_from_slice(&self.pixels[start..start + width]);
+        }
+        Mask { pixels, width, height }
+    }
+
+    // Erosion (all set) or dilation (any set) over a square window, done
+    // as a horizontal pass and then a vertical one.
+    fn square_filter(&self, radius: usize, erode: bool) -> Mask {
+        if radius == 0 {
+            return self.clone();
+        }
+        let (width, height) = (self.width, self.height);
+        let window_size = 2 * radius + 1;
+        let window = |pixels: &[bool], index: &dyn Fn(usize) -> usize, len: usize, out: &mut [bool]| {
+            // Running count of set pixels in the window, where anything
+            // past the ends is unset
+            let mut set = 0;
+            for i in 0..(radius + 1).min(len) {
+                set += pixels[index(i)] as usize;
+            }
+            for i in 0..len {
+                out[index(i)] = if erode { set == window_size } else { set > 0 };
+                if i + radius + 1 < len {
+                    set += pixels[index(i + radius + 1)] as usize;
+                }
+                if i >= radius {
+                    set -= pixels[index(i - radius)] as usize;
+                }
+            }
+        };
+
+        let mut horizontal = vec![false; self.pixels.len()];
+        for y in 0..height {
+            window(&self.pixels, &|x| y * width + x, width, &mut horizontal);
+        }
+        let mut pixels = vec![false; self.pixels.len()];
+        for x in 0..width {
+            window(&horizontal, &|y| y * width + x, height, &mut pixels);
+        }
+        Mask { pixels, width, height }
+    }
+
+    /// A user map with `id` on the set pixels and background elsewhere.
+    pub fn to_user_map(&self, id: NiteUserId) -> OwnedUserMap {
+        let pixels = self.pixels.iter().map(|&px| if px { id } else { 0 }).collect();
+        OwnedUserMap::new(self.width, self.height, pixels)
+    }
+}
+
+/// Inclusive pixel bounds.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PixelBounds {
+    pub min_x: usize,
+    pub min_y: usize,
+    pub max_x: usize,
+    pub max_y: usize,
+}
+
+impl PixelBounds {
+    pub fn width(&self) -> usize {
+        self.max_x - self.min_x + 1
+    }
+
+    pub fn height(&self) -> usize {
+        self.max_y - self.min_y + 1
+    }
+
+    pub fn contains(&self, x: usize, y: usize) -> bool {
+        (self.min_x..=self.max_x).contains(&x) && (self.min_y..=self.max_y).contains(&y)
+    }
+}
+
+/// The pixels of one user in a user map.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct UserRegion {
+    id: NiteUserId,
+    pixel_count: usize,
+    centroid: (f32, f32),
+    bounds: PixelBounds,
+}
+
+impl UserRegion {
+    pub fn id(&self) -> NiteUserId {
+        self.id
+    }
+
+    pub fn pixel_count(&self) -> usize {
+        self.pixel_count
+    }
+
+    /// Mean `(x, y)` of the user's pixels.
+    pub fn centroid(&self) -> (f32, f32) {
+        self.centroid
+    }
+
+    pub fn bounds(&self) -> PixelBounds {
+        self.bounds
+    }
+}
+
+// Running totals for a UserRegion while scanning a user map.
+#[derive(Clone, Copy)]
+pub(crate) struct RegionAccumulator {
+    pixel_count: usize,
+    sum_x: u64,
+    sum_y: u64,
+    bounds: PixelBounds,
+}
+
+impl RegionAccumulator {
+    pub(crate) fn new(x: usize, y: usize) -> RegionAccumulator {
+        RegionAccumulator {
+            pixel_count: 0,
+            sum_x: 0,
+            sum_y: 0,
+            bounds: PixelBounds { min_x: x, min_y: y, max_x: x, max_y: y },
+        }
+    }
+
+    pub(crate) fn add(&mut self, x: usize, y: usize) {
+        self.pixel_count += 1;
+        self.sum_x += x as u64;
+        self.sum_y += y as u64;
+        self.bounds.min_x = self.bounds.min_x.min(x);
+        self.bounds.min_y = self.bounds.min_y.min(y);
+        self.bounds.max_x = self.bounds.max_x.max(x);
+        self.bounds.max_y = self.bounds.max_y.max(y);
+    }
+
+    pub(crate) fn finish(&self, id: NiteUserId) -> UserRegion {
+        let count = self.pixel_count as f64;
+        UserRegion {
+            id,
+            pixel_count: self.pixel_count,
+            centroid: ((self.sum_x as f64 / count) as f32, (self.sum_y as f64 / count) as f32),
+            bounds: self.bounds,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mask(rows: &[&str]) -> Mask {
+        let pixels = rows.iter().flat_map(|row| row.chars().map(|c| c == '#')).collect();
+        Mask::from_pixels(rows[0].len(), rows.len(), pixels)
+    }
+
+    fn rows(mask: &Mask) -> Vec<String> {
+        mask.pixels().chunks(mask.width())
+            .map(|row| row.iter().map(|&px| if px { '#' } else { '.' }).collect())
+            .collect()
+    }
+
+    #[test]
+    fn erode() {
+        let square = mask(&[
+            ".......",
+            ".#####.",
+            ".#####.",
+            ".#####.",
+            ".......",
+        ]);
+        assert_eq!(rows(&square.erode(1)), vec![
+            ".......",
+            ".......",
+            "..###..",
+            ".......",
+            ".......",
+        ]);
+        assert!(square.erode(2).is_empty());
+        assert_eq!(square.erode(0), square);
+    }
+
+    #[test]
+    fn erode_from_edges() {
+        let full = mask(&["####", "####", "####", "####"]);
+        assert_eq!(rows(&full.erode(1)), vec!["....", ".##.", ".##.", "...."]);
+    }
+
+    #[test]
+    fn dilate() {
+        let dot = mask(&[".....", ".....", "..#..", ".....", "#...."]);
+        assert_eq!(rows(&dot.dilate(1)), vec![
+            ".....",
+            ".###.",
+            ".###.",
+            "####.",
+            "##...",
+        ]);
+        assert_eq!(dot.dilate(0), dot);
+    }
+
+    #[test]
+    fn open() {
+        let speckled = mask(&[
+            "#......",
+            "..####.",
+            "..####.",
+            "..####.",
+            "..####.",
+            "......#",
+        ]);
+        assert_eq!(rows(&speckled.open(1)), vec![
+            ".......",
+            "..####.",
+            "..####.",
+            "..####.",
+            "..####.",
+            ".......",
+        ]);
+
+        let full = mask(&["####", "####", "####"]);
+        assert_eq!(full.open(1), full);
+    }
+
+    #[test]
+    fn close() {
+        let ring = mask(&[
+            ".......",
+            ".#####.",
+            ".#...#.",
+            ".#...#.",
+            ".#####.",
+            ".......",
+        ]);
+        assert_eq!(rows(&ring.close(1)), vec![
+            ".......",
+            ".#####.",
+            ".#####.",
+            ".#####.",
+            ".#####.",
+            ".......",
+        ]);
+
+        // Closing neither grows regions out to the edge of the mask nor
+        // wears them away from it
+        let small_ring = mask(&["....", ".###", ".#.#", ".###"]);
+        assert_eq!(rows(&small_ring.close(1)), vec!["....", ".###", ".###", ".###"]);
+        let full = mask(&["###", "###"]);
+        assert_eq!(full.close(2), full);
+    }
+
+    #[test]
+    fn remove_small_blobs() {
+        let blobs = mask(&[
+            "#...##",
+            "....##",
+            ".#....",
+            "#.....",
+        ]);
+        assert_eq!(rows(&blobs.remove_small_blobs(2)), vec![
+            "....##",
+            "....##",
+            "......",
+            "......",
+        ]);
+        assert_eq!(blobs.remove_small_blobs(1), blobs);
+    }
+}
@@ -0,0 +1,250 @@
+use std::fs::File;
+use std::io::{self, Write, BufWriter};
+use std::path::Path;
+use frame_source::{TrackerFrame, DepthMap};
+use types::WorldPoint;
+use user_tracker::UserData;
+use util::base64_encode;
+
+// An unchunked MCAP file without a summary section: viewers read it
+// front to back, which is all a session dump needs.
+const MAGIC: &[u8; 8] = b"\x89MCAP0\r\n";
+
+const OP_HEADER: u8 = 0x01;
+const OP_FOOTER: u8 = 0x02;
+const OP_SCHEMA: u8 = 0x03;
+const OP_CHANNEL: u8 = 0x04;
+const OP_MESSAGE: u8 = 0x05;
+const OP_DATA_END: u8 = 0x0f;
+
+const USERS_CHANNEL: u16 = 1;
+const FLOOR_CHANNEL: u16 = 2;
+const USER_MAP_CHANNEL: u16 = 3;
+const DEPTH_CHANNEL: u16 = 4;
+
+const USERS_SCHEMA: &str = r##"{"title":"nite2.Users","type":"object","properties":{"timestamp":{"$ref":"#/$defs/time"},"frame_index":{"type":"integer"},"users":{"type":"array","items":{"type":"object","properties":{"id":{"type":"integer"},"visible":{"type":"boolean"},"skeleton_state":{"type":"string"},"center_of_mass":{"$ref":"#/$defs/point"},"joints":{"type":"array","items":{"type":"object","properties":{"joint":{"type":"string"},"position":{"$ref":"#/$defs/point"},"position_confidence":{"type":"number"},"orientation":{"type":"object","properties":{"x":{"type":"number"},"y":{"type":"number"},"z":{"type":"number"},"w":{"type":"number"}}},"orientation_confidence":{"type":"number"}}}}}}}},"$defs":{"time":{"type":"object","properties":{"sec":{"type":"integer"},"nsec":{"type":"integer"}}},"point":{"type":"object","properties":{"x":{"type":"number"},"y":{"type":"number"},"z":{"type":"number"}}}}}"##;
+
+const FLOOR_SCHEMA: &str = r##"{"title":"nite2.Floor","type":"object","properties":{"timestamp":{"$ref":"#/$defs/time"},"point":{"$ref":"#/$defs/point"},"normal":{"$ref":"#/$defs/point"},"confidence":{"type":"number"}},"$defs":{"time":{"type":"object","properties":{"sec":{"type":"integer"},"nsec":{"type":"integer"}}},"point":{"type":"object","properties":{"x":{"type":"number"},"y":{"type":"number"},"z":{"type":"number"}}}}}"##;
+
+const RAW_IMAGE_SCHEMA: &str = r#"{"title":"foxglove.RawImage","type":"object","properties":{"timestamp":{"type":"object","properties":{"sec":{"type":"integer","minimum":0},"nsec":{"type":"integer","minimum":0,"maximum":999999999}}},"frame_id":{"type":"string"},"width":{"type":"integer","minimum":0},"height":{"type":"integer","minimum":0},"encoding":{"type":"string"},"step":{"type":"integer","minimum":0},"data":{"type":"string","contentEncoding":"base64"}}}"#;
+
+/// Writes user tracker frames to an MCAP file with JSON messages on these
+/// topics, timestamped with the frame timestamps:
+///
+/// * `/users`: every user with their skeleton joints, in millimeters
+/// * `/floor`: the floor plane and its confidence
+/// * `/user_map`: the user map as a `foxglove.RawImage` of user ids
+/// * `/depth`: the depth image as a `foxglove.RawImage`, when passed in
+pub struct McapWriter<W: Write> {
+    writer: W,
+    sequence: u32,
+}
+
+impl McapWriter<BufWriter<File>> {
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<McapWriter<BufWriter<File>>> {
+        McapWriter::new(BufWriter::new(File::create(path)?))
+    }
+}
+
+impl<W: Write> McapWriter<W> {
+    pub fn new(mut writer: W) -> io::Result<McapWriter<W>> {
+        writer.write_all(MAGIC)?;
+
+        let mut header = Vec::new();
+        push_string(&mut header, "");
+        push_string(&mut header, "nite2");
+        write_record(&mut writer, OP_HEADER, &header)?;
+
+        let channels = [
+            (USERS_CHANNEL, "/users", "nite2.Users", USERS_SCHEMA),
+            (FLOOR_CHANNEL, "/floor", "nite2.Floor", FLOOR_SCHEMA),
+            (USER_MAP_CHANNEL, "/user_map", "foxglove.RawImage", RAW_IMAGE_SCHEMA),
+            (DEPTH_CHANNEL, "/depth", "foxglove.RawImage", RAW_IMAGE_SCHEMA),
+        ];
+        for &(id, topic, schema_name, schema) in channels.iter() {
+            // One schema per channel, sharing the channel's id
+            let mut record = Vec::new();
+            record.extend_from_slice(&id.to_le_bytes());
+            push_string(&mut record, schema_name);
+            push_string(&mut record, "jsonschema");
+            push_string(&mut record, schema);
+            write_record(&mut writer, OP_SCHEMA, &record)?;
+
+            let mut record = Vec::new();
+            record.extend_from_slice(&id.to_le_bytes());
+            record.extend_from_slice(&id.to_le_bytes());
+            push_string(&mut record, topic);
+            push_string(&mut record, "json");
+            // Empty metadata map
+            record.extend_from_slice(&0u32.to_le_bytes());
+            write_record(&mut writer, OP_CHANNEL, &record)?;
+        }
+
+        Ok(McapWriter {
+            writer,
+            sequence: 0,
+        })
+    }
+
+    /// Writes one frame. Pass the frame's depth pixels (see
+    /// `DepthMap::from_frame`) to include them on `/depth`.
+    pub fn write_frame<F: TrackerFrame>(&mut self, frame: &F, depth: Option<DepthMap>) -> io::Result<()> {
+        let time = frame.timestamp() * 1000;
+        let stamp = format!(r#"{{"sec":{},"nsec":{}}}"#, time / 1_000_000_000, time % 1_000_000_000);
+
+        let users: Vec<String> = frame.users().iter().map(user_json).collect();
+        let message = format!(r#"{{"timestamp":{},"frame_index":{},"users":[{}]}}"#,
+            stamp, frame.frame_index(), users.join(","));
+        self.write_message(USERS_CHANNEL, time, &message)?;
+
+        let floor = frame.floor();
+        let message = format!(r#"{{"timestamp":{},"point":{},"normal":{},"confidence":{}}}"#,
+            stamp, point_json(floor.point.into()), point_json(floor.normal.into()), frame.floor_confidence());
+        self.write_message(FLOOR_CHANNEL, time, &message)?;
+
+        let user_map = frame.user_map();
+        let pixels: Vec<u8> = user_map.pixels().flat_map(|id| id.to_le_bytes()).collect();
+        let message = raw_image_json(&stamp, "user_map", user_map.width(), user_map.height(), "16UC1", &pixels);
+        self.write_message(USER_MAP_CHANNEL, time, &message)?;
+
+        if let Some(depth) = depth {
+            let pixels: Vec<u8> = depth.pixels.iter().flat_map(|px| px.to_le_bytes()).collect();
+            let message = raw_image_json(&stamp, "depth", depth.width, depth.height, "16UC1", &pixels);
+            self.write_message(DEPTH_CHANNEL, time, &message)?;
+        }
+        Ok(())
+    }
+
+    /// Ends the data section and writes the footer. The file isn't a valid
+    /// MCAP file until this is called.
+    pub fn finish(mut self) -> io::Result<W> {
+        // A CRC of 0 means none was computed
+        write_record(&mut self.writer, OP_DATA_END, &0u32.to_le_bytes())?;
+        write_record(&mut self.writer, OP_FOOTER, &[0u8; 20])?;
+        self.writer.write_all(MAGIC)?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+
+    fn write_message(&mut self, channel: u16, time: u64, message: &str) -> io::Result<()> {
+        let mut record = Vec::with_capacity(22 + message.len());
+        record.extend_from_slice(&channel.to_le_bytes());
+        record.extend_from_slice(&self.sequence.to_le_bytes());
+        record.extend_from_slice(&time.to_le_bytes());
+        record.extend_from_slice(&time.to_le_bytes());
+        record.extend_from_slice(message.as_bytes());
+        self.sequence = self.sequence.wrapping_add(1);
+        write_record(&mut self.writer, OP_MESSAGE, &record)
+    }
+}
+
+fn write_record<W: Write>(writer: &mut W, opcode: u8, content: &[u8]) -> io::Result<()> {
+    writer.write_all(&[opcode])?;
+    writer.write_all(&(content.len() as u64).to_le_bytes())?;
+    writer.write_all(content)
+}
+
+fn push_string(record: &mut Vec<u8>, s: &str) {
+    record.extend_from_slice(&(s.len() as u32).to_le_bytes());
+    record.extend_from_slice(s.as_bytes());
+}
+
+fn point_json(point: WorldPoint) -> String {
+    format!(r#"{{"x":{},"y":{},"z":{}}}"#, point.x, point.y, point.z)
+}
+
+fn user_json(user: &UserData) -> String {
+    let joints: Vec<String> = match user.skeleton() {
+        Ok(skeleton) => skeleton.joints().iter().map(|joint| {
+            let orientation = joint.orientation();
+            format!(
+                r#"{{"joint":"{:?}","position":{},"position_confidence":{},"orientation":{{"x":{},"y":{},"z":{},"w":{}}},"orientation_confidence":{}}}"#,
+                joint.joint_type(), point_json(joint.position()), joint.position_confidence(),
+                orientation.x, orientation.y, orientation.z, orientation.w, joint.orientation_confidence(),
+            )
+        }).collect(),
+        Err(_) => Vec::new(),
+    };
+    format!(
+        r#"{{"id":{},"visible":{},"skeleton_state":"{:?}","center_of_mass":{},"joints":[{}]}}"#,
+        user.id(), user.is_visible(), user.skeleton_state(), point_json(user.center_of_mass()), joints.join(","),
+    )
+}
+
+fn raw_image_json(stamp: &str, frame_id: &str, width: usize, height: usize, encoding: &str, data: &[u8]) -> String {
+    format!(
+        r#"{{"timestamp":{},"frame_id":"{}","width":{},"height":{},"encoding":"{}","step":{},"data":"{}"}}"#,
+        stamp, frame_id, width, height, encoding, width * 2, base64_encode(data),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use frame_source::OwnedFrame;
+    use mock::MockUser;
+    use user_map::OwnedUserMap;
+
+    fn u16_at(bytes: &[u8], at: usize) -> u16 {
+        u16::from_le_bytes([bytes[at], bytes[at + 1]])
+    }
+
+    #[test]
+    fn record_layout() {
+        let mut mcap = McapWriter::new(Vec::new()).expect("writing to a Vec");
+        for i in 0..2 {
+            let mut frame = OwnedFrame::new(i, 1_000 + i as u64 * 33_333);
+            frame.users.push(MockUser::new(1).into());
+            frame.user_map = OwnedUserMap::new(2, 1, vec![0, 1]);
+            let depth = [500, 600];
+            let depth = if i == 1 { Some(DepthMap { pixels: &depth, width: 2, height: 1 }) } else { None };
+            mcap.write_frame(&frame, depth).expect("writing to a Vec");
+        }
+        let bytes = mcap.finish().expect("writing to a Vec");
+
+        assert_eq!(&bytes[..8], MAGIC);
+        assert_eq!(&bytes[bytes.len() - 8..], MAGIC);
+
+        // Each record's length lands exactly on the next record, and the
+        // last one on the closing magic
+        let mut records = Vec::new();
+        let mut at = 8;
+        while at < bytes.len() - 8 {
+            let mut length = [0u8; 8];
+            length.copy_from_slice(&bytes[at + 1..at + 9]);
+            let length = u64::from_le_bytes(length) as usize;
+            records.push((bytes[at], &bytes[at + 9..at + 9 + length]));
+            at += 9 + length;
+        }
+        assert_eq!(at, bytes.len() - 8);
+
+        let opcodes: Vec<u8> = records.iter().map(|&(opcode, _)| opcode).collect();
+        assert_eq!(opcodes[0], OP_HEADER);
+        assert_eq!(&opcodes[opcodes.len() - 2..], &[OP_DATA_END, OP_FOOTER]);
+
+        let mut schemas = Vec::new();
+        let mut channels = Vec::new();
+        let mut messages = Vec::new();
+        for &(opcode, content) in &records {
+            match opcode {
+                OP_SCHEMA => schemas.push(u16_at(content, 0)),
+                OP_CHANNEL => {
+                    // A channel's schema must already have been written
+                    assert!(schemas.contains(&u16_at(content, 2)));
+                    channels.push(u16_at(content, 0));
+                },
+                OP_MESSAGE => {
+                    assert!(channels.contains(&u16_at(content, 0)), "message on an unknown channel");
+                    assert!(content[22..].starts_with(br#"{"timestamp":{"sec":0,"nsec":"#));
+                    messages.push(u16_at(content, 0));
+                },
+                _ => {},
+            }
+        }
+        assert_eq!(channels, vec![USERS_CHANNEL, FLOOR_CHANNEL, USER_MAP_CHANNEL, DEPTH_CHANNEL]);
+        assert_eq!(messages, vec![
+            USERS_CHANNEL, FLOOR_CHANNEL, USER_MAP_CHANNEL,
+            USERS_CHANNEL, FLOOR_CHANNEL, USER_MAP_CHANNEL, DEPTH_CHANNEL,
+        ]);
+    }
+}
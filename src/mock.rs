@@ -0,0 +1,116 @@
+use std::cell::RefCell;
+use std::collections::{BTreeSet, VecDeque};
+use std::mem;
+use nite2_sys::*;
+use frame_source::{FrameSource, OwnedFrame};
+use types::{Status, JointType, SkeletonState, PoseType, UserState, WorldPoint, DepthPoint};
+use user_tracker::UserData;
+
+/// A `FrameSource` that plays back scripted frames, for exercising code
+/// without a sensor or the NiTE runtime. Once every frame has been read,
+/// `read_frame` returns `Status::OutOfFlow`.
+#[derive(Debug, Default)]
+pub struct MockFrameSource {
+    frames: RefCell<VecDeque<OwnedFrame>>,
+    tracked_skeletons: RefCell<BTreeSet<NiteUserId>>,
+}
+
+impl MockFrameSource {
+    pub fn new(frames: Vec<OwnedFrame>) -> MockFrameSource {
+        MockFrameSource {
+            frames: RefCell::new(frames.into()),
+            tracked_skeletons: RefCell::new(BTreeSet::new()),
+        }
+    }
+
+    pub fn push_frame(&self, frame: OwnedFrame) {
+        self.frames.borrow_mut().push_back(frame);
+    }
+
+    pub fn remaining_frames(&self) -> usize {
+        self.frames.borrow().len()
+    }
+}
+
+impl FrameSource for MockFrameSource {
+    type Frame<'s> = OwnedFrame where Self: 's;
+
+    fn read_frame(&self) -> Result<OwnedFrame, Status> {
+        self.frames.borrow_mut().pop_front().ok_or(Status::OutOfFlow)
+    }
+
+    fn track_skeleton(&self, user: NiteUserId, setting: bool) -> Result<(), Status> {
+        if setting {
+            self.tracked_skeletons.borrow_mut().insert(user);
+        } else {
+            self.tracked_skeletons.borrow_mut().remove(&user);
+        }
+        Ok(())
+    }
+
+    fn tracking_skeleton(&self, user: NiteUserId) -> bool {
+        self.tracked_skeletons.borrow().contains(&user)
+    }
+}
+
+/// Scripts the `UserData` for one user in a mock frame. A new `MockUser`
+/// is visible, with no skeleton and every joint at the origin.
+#[derive(Clone, Copy, Debug)]
+pub struct MockUser(NiteUserData);
+
+impl MockUser {
+    pub fn new(id: NiteUserId) -> MockUser {
+        let mut user_data: NiteUserData = unsafe { mem::zeroed() };
+        user_data.id = id;
+        user_data.state = UserState::VISIBLE.bits();
+        for (i, joint) in user_data.skeleton.joints.iter_mut().enumerate() {
+            joint.jointType = i as NiteJointType;
+            joint.orientation.w = 1.0;
+        }
+        user_data.poses[PoseType::Psi as usize].type_ = NITE_POSE_PSI;
+        user_data.poses[PoseType::CrossedHands as usize].type_ = NITE_POSE_CROSSED_HANDS;
+        MockUser(user_data)
+    }
+
+    pub fn state(mut self, state: UserState) -> MockUser {
+        self.0.state = state.bits();
+        self
+    }
+
+    pub fn center_of_mass(mut self, point: WorldPoint) -> MockUser {
+        self.0.centerOfMass = NitePoint3f { x: point.x, y: point.y, z: point.z };
+        self
+    }
+
+    pub fn bounding_box(mut self, min: DepthPoint, max: DepthPoint) -> MockUser {
+        self.0.boundingBox = NiteBoundingBox {
+            min: NitePoint3f { x: min.x, y: min.y, z: min.z },
+            max: NitePoint3f { x: max.x, y: max.y, z: max.z },
+        };
+        self
+    }
+
+    pub fn skeleton_state(mut self, state: SkeletonState) -> MockUser {
+        self.0.skeleton.state = state as NiteSkeletonState;
+        self
+    }
+
+    pub fn joint(mut self, joint_type: JointType, position: WorldPoint, confidence: f32) -> MockUser {
+        let joint = &mut self.0.skeleton.joints[joint_type as usize];
+        joint.position = NitePoint3f { x: position.x, y: position.y, z: position.z };
+        joint.positionConfidence = confidence;
+        joint.orientationConfidence = confidence;
+        self
+    }
+
+    pub fn joint_orientation(mut self, joint_type: JointType, orientation: NiteQuaternion) -> MockUser {
+        self.0.skeleton.joints[joint_type as usize].orientation = orientation;
+        self
+    }
+}
+
+impl From<MockUser> for UserData {
+    fn from(user: MockUser) -> UserData {
+        user.0.into()
+    }
+}
@@ -0,0 +1,236 @@
+use std::ops::Mul;
+use nite2_sys::NiteQuaternion;
+use types::WorldPoint;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Quaternion {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+    pub w: f32,
+}
+
+impl Quaternion {
+    pub fn new(x: f32, y: f32, z: f32, w: f32) -> Quaternion {
+        Quaternion { x, y, z, w }
+    }
+
+    pub fn identity() -> Quaternion {
+        Quaternion { x: 0.0, y: 0.0, z: 0.0, w: 1.0 }
+    }
+
+    /// A rotation of `angle` radians around `axis`, which doesn't need to be
+    /// normalized.
+    pub fn from_axis_angle(axis: WorldPoint, angle: f32) -> Quaternion {
+        let length = axis.length();
+        if length == 0.0 {
+            return Quaternion::identity();
+        }
+        let s = (angle / 2.0).sin() / length;
+        Quaternion {
+            x: axis.x * s,
+            y: axis.y * s,
+            z: axis.z * s,
+            w: (angle / 2.0).cos(),
+        }
+    }
+
+    pub fn dot(&self, other: Quaternion) -> f32 {
+        self.x * other.x + self.y * other.y + self.z * other.z + self.w * other.w
+    }
+
+    pub fn norm(&self) -> f32 {
+        self.dot(*self).sqrt()
+    }
+
+    /// NiTE reports an all-zero orientation for joints it has no orientation
+    /// for, so normalizing a zero quaternion gives the identity.
+    pub fn normalize(&self) -> Quaternion {
+        let norm = self.norm();
+        if norm == 0.0 {
+            Quaternion::identity()
+        } else {
+            Quaternion {
+                x: self.x / norm,
+                y: self.y / norm,
+                z: self.z / norm,
+                w: self.w / norm,
+            }
+        }
+    }
+
+    pub fn conjugate(&self) -> Quaternion {
+        Quaternion { x: -self.x, y: -self.y, z: -self.z, w: self.w }
+    }
+
+    pub fn inverse(&self) -> Quaternion {
+        let norm_squared = self.dot(*self);
+        if norm_squared == 0.0 {
+            return Quaternion::identity();
+        }
+        let conjugate = self.conjugate();
+        Quaternion {
+            x: conjugate.x / norm_squared,
+            y: conjugate.y / norm_squared,
+            z: conjugate.z / norm_squared,
+            w: conjugate.w / norm_squared,
+        }
+    }
+
+    /// Spherical linear interpolation along the shortest arc, where `t` of
+    /// 0.0 gives `self` and 1.0 gives `other`.
+    pub fn slerp(&self, other: Quaternion, t: f32) -> Quaternion {
+        let from = self.normalize();
+        let mut to = other.normalize();
+        let mut cos_theta = from.dot(to);
+        if cos_theta < 0.0 {
+            to = Quaternion { x: -to.x, y: -to.y, z: -to.z, w: -to.w };
+            cos_theta = -cos_theta;
+        }
+
+        let (from_weight, to_weight) = if cos_theta > 0.9995 {
+            // Close enough that sin(theta) is unstable; lerp instead
+            (1.0 - t, t)
+        } else {
+            let theta = cos_theta.acos();
+            let sin_theta = theta.sin();
+            (((1.0 - t) * theta).sin() / sin_theta, (t * theta).sin() / sin_theta)
+        };
+
+        Quaternion {
+            x: from.x * from_weight + to.x * to_weight,
+            y: from.y * from_weight + to.y * to_weight,
+            z: from.z * from_weight + to.z * to_weight,
+            w: from.w * from_weight + to.w * to_weight,
+        }.normalize()
+    }
+
+    pub fn rotate(&self, point: WorldPoint) -> WorldPoint {
+        let q = self.normalize();
+        let p = Quaternion { x: point.x, y: point.y, z: point.z, w: 0.0 };
+        let rotated = q * p * q.conjugate();
+        WorldPoint { x: rotated.x, y: rotated.y, z: rotated.z }
+    }
+
+    /// The equivalent row-major 3x3 rotation matrix.
+    pub fn to_rotation_matrix(&self) -> [[f32; 3]; 3] {
+        let Quaternion { x, y, z, w } = self.normalize();
+        [
+            [1.0 - 2.0 * (y * y + z * z), 2.0 * (x * y - z * w), 2.0 * (x * z + y * w)],
+            [2.0 * (x * y + z * w), 1.0 - 2.0 * (x * x + z * z), 2.0 * (y * z - x * w)],
+            [2.0 * (x * z - y * w), 2.0 * (y * z + x * w), 1.0 - 2.0 * (x * x + y * y)],
+        ]
+    }
+
+    /// Returns `(x, y, z)` rotations in radians around each axis, such that
+    /// this rotation is `Rz(z) * Ry(y) * Rx(x)`.
+    pub fn to_euler_angles(&self) -> (f32, f32, f32) {
+        let m = self.to_rotation_matrix();
+        let y = (-m[2][0]).clamp(-1.0, 1.0).asin();
+        if m[2][0].abs() < 0.9999 {
+            (m[2][1].atan2(m[2][2]), y, m[1][0].atan2(m[0][0]))
+        } else {
+            // Gimbal lock: x and z rotate around the same axis
+            (0.0, y, (-m[0][1]).atan2(m[1][1]))
+        }
+    }
+}
+
+impl Mul for Quaternion {
+    type Output = Quaternion;
+
+    fn mul(self, rhs: Quaternion) -> Quaternion {
+        Quaternion {
+            x: self.w * rhs.x + self.x * rhs.w + self.y * rhs.z - self.z * rhs.y,
+            y: self.w * rhs.y - self.x * rhs.z + self.y * rhs.w + self.z * rhs.x,
+            z: self.w * rhs.z + self.x * rhs.y - self.y * rhs.x + self.z * rhs.w,
+            w: self.w * rhs.w - self.x * rhs.x - self.y * rhs.y - self.z * rhs.z,
+        }
+    }
+}
+
+impl From<NiteQuaternion> for Quaternion {
+    fn from(q: NiteQuaternion) -> Quaternion {
+        Quaternion { x: q.x, y: q.y, z: q.z, w: q.w }
+    }
+}
+
+impl From<Quaternion> for NiteQuaternion {
+    fn from(q: Quaternion) -> NiteQuaternion {
+        NiteQuaternion { x: q.x, y: q.y, z: q.z, w: q.w }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::{FRAC_PI_2, FRAC_PI_4};
+
+    fn assert_close(a: Quaternion, b: Quaternion) {
+        assert!((a.x - b.x).abs() < 1e-5 && (a.y - b.y).abs() < 1e-5
+            && (a.z - b.z).abs() < 1e-5 && (a.w - b.w).abs() < 1e-5, "{:?} != {:?}", a, b);
+    }
+
+    fn about(x: f32, y: f32, z: f32, angle: f32) -> Quaternion {
+        Quaternion::from_axis_angle(WorldPoint::new(x, y, z), angle)
+    }
+
+    #[test]
+    fn hamilton_product() {
+        let i = Quaternion::new(1.0, 0.0, 0.0, 0.0);
+        let j = Quaternion::new(0.0, 1.0, 0.0, 0.0);
+        let k = Quaternion::new(0.0, 0.0, 1.0, 0.0);
+        assert_eq!(i * j, k);
+        assert_eq!(j * i, Quaternion::new(0.0, 0.0, -1.0, 0.0));
+        assert_eq!(i * i, Quaternion::new(0.0, 0.0, 0.0, -1.0));
+        assert_eq!(i * j * k, Quaternion::new(0.0, 0.0, 0.0, -1.0));
+    }
+
+    #[test]
+    fn inverse() {
+        let q = Quaternion::new(1.0, 2.0, 3.0, 4.0);
+        assert_close(q * q.inverse(), Quaternion::identity());
+        assert_close(q.inverse() * q, Quaternion::identity());
+        assert_eq!(Quaternion::new(0.0, 0.0, 0.0, 0.0).inverse(), Quaternion::identity());
+    }
+
+    #[test]
+    fn slerp() {
+        let from = Quaternion::identity();
+        let to = about(0.0, 0.0, 1.0, FRAC_PI_2);
+        assert_close(from.slerp(to, 0.0), from);
+        assert_close(from.slerp(to, 1.0), to);
+        assert_close(from.slerp(to, 0.5), about(0.0, 0.0, 1.0, FRAC_PI_4));
+
+        // -to is the same rotation, and is still reached the short way round
+        let negated = Quaternion::new(-to.x, -to.y, -to.z, -to.w);
+        assert_close(from.slerp(negated, 0.5), about(0.0, 0.0, 1.0, FRAC_PI_4));
+    }
+
+    #[test]
+    fn rotation_matrix() {
+        let q = about(0.0, 0.0, 1.0, FRAC_PI_2);
+        let expected = [[0.0, -1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 1.0]];
+        for (row, expected_row) in q.to_rotation_matrix().iter().zip(expected.iter()) {
+            for (value, expected_value) in row.iter().zip(expected_row.iter()) {
+                assert!((value - expected_value).abs() < 1e-6);
+            }
+        }
+        let rotated = q.rotate(WorldPoint::new(1.0, 0.0, 0.0));
+        assert!((rotated - WorldPoint::new(0.0, 1.0, 0.0)).length() < 1e-6);
+    }
+
+    #[test]
+    fn euler_angles() {
+        let q = about(0.0, 0.0, 1.0, 0.3) * about(0.0, 1.0, 0.0, 0.2) * about(1.0, 0.0, 0.0, 0.1);
+        let (x, y, z) = q.to_euler_angles();
+        assert!((x - 0.1).abs() < 1e-5 && (y - 0.2).abs() < 1e-5 && (z - 0.3).abs() < 1e-5);
+
+        // Pitched straight up, all of the roll is reported around z
+        let q = about(0.0, 0.0, 1.0, 0.3) * about(0.0, 1.0, 0.0, FRAC_PI_2);
+        let (x, y, z) = q.to_euler_angles();
+        assert_eq!(x, 0.0);
+        assert!((y - FRAC_PI_2).abs() < 1e-3 && (z - 0.3).abs() < 1e-5);
+    }
+}
@@ -0,0 +1,591 @@
+use std::cell::{Cell, RefCell};
+use std::convert::TryFrom;
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::io::{self, Read, Write, BufReader, BufWriter};
+use std::path::Path;
+use std::time::{Duration, Instant};
+use std::mem;
+use nite2_sys::*;
+use openni2::OniDepthPixel;
+use frame_source::{FrameSource, TrackerFrame, OwnedFrame, DepthMap, OwnedDepthMap};
+use types::{Status, UserState};
+use user_map::OwnedUserMap;
+use user_tracker::{
+    UserData,
+    NITE_POSE_STATE_DETECTED,
+    NITE_POSE_STATE_IN_POSE,
+    NITE_POSE_STATE_ENTER,
+    NITE_POSE_STATE_EXIT,
+};
+
+// File layout, all little endian:
+//
+//   header:   b"NITE2REC", u16 version
+//   frame:    u64 timestamp, u32 frame index, f32 floor confidence,
+//             6 x f32 floor (point, normal), u16 user count, users,
+//             u8 flags, [user map], [depth]
+//   user:     i16 id, i32 state, 6 x f32 bounding box, 3 x f32 center of mass,
+//             i32 skeleton state, 15 x joint, 2 x i32 pose state
+//   joint:    3 x f32 position, f32 position confidence,
+//             4 x f32 orientation (x, y, z, w), f32 orientation confidence
+//   user map: u16 width, u16 height, u32 run count, runs of (i16 user id, u32 length)
+//   depth:    u16 width, u16 height, width * height x u16
+const MAGIC: &[u8; 8] = b"NITE2REC";
+const VERSION: u16 = 1;
+
+const FLAG_USER_MAP: u8 = 1;
+const FLAG_DEPTH: u8 = 2;
+
+// Valid values of the recorded skeleton and pose states
+const SKELETON_STATES: [i32; 8] = [
+    NITE_SKELETON_NONE,
+    NITE_SKELETON_CALIBRATING,
+    NITE_SKELETON_TRACKED,
+    NITE_SKELETON_CALIBRATION_ERROR_NOT_IN_POSE,
+    NITE_SKELETON_CALIBRATION_ERROR_HANDS,
+    NITE_SKELETON_CALIBRATION_ERROR_HEAD,
+    NITE_SKELETON_CALIBRATION_ERROR_LEGS,
+    NITE_SKELETON_CALIBRATION_ERROR_TORSO,
+];
+const POSE_STATE_BITS: i32 = NITE_POSE_STATE_DETECTED | NITE_POSE_STATE_IN_POSE | NITE_POSE_STATE_ENTER | NITE_POSE_STATE_EXIT;
+
+/// Writes user tracker frames to a recording that `Player` can replay.
+pub struct Recorder<W: Write> {
+    writer: W,
+    record_user_map: bool,
+}
+
+impl Recorder<BufWriter<File>> {
+    pub fn create<P: AsRef<Path>>(path: P, record_user_map: bool) -> io::Result<Recorder<BufWriter<File>>> {
+        Recorder::new(BufWriter::new(File::create(path)?), record_user_map)
+    }
+}
+
+impl<W: Write> Recorder<W> {
+    pub fn new(mut writer: W, record_user_map: bool) -> io::Result<Recorder<W>> {
+        writer.write_all(MAGIC)?;
+        write_u16(&mut writer, VERSION)?;
+        Ok(Recorder {
+            writer,
+            record_user_map,
+        })
+    }
+
+    /// Records one frame. Pass the frame's depth pixels (see
+    /// `DepthMap::from_frame`) to include them in the recording. Frames that
+    /// don't fit the format, or depth maps whose pixels don't match their
+    /// dimensions, are rejected before anything is written.
+    pub fn record<F: TrackerFrame>(&mut self, frame: &F, depth: Option<DepthMap>) -> io::Result<()> {
+        let frame_index = checked(frame.frame_index(), "frame index")?;
+        let users = frame.users();
+        let user_count = checked(users.len(), "user count")?;
+        let user_map = frame.user_map();
+        let user_map_size = if self.record_user_map {
+            Some((checked(user_map.width(), "user map width")?, checked(user_map.height(), "user map height")?))
+        } else {
+            None
+        };
+        let depth_size = match depth {
+            Some(depth) => {
+                if depth.pixels.len() != depth.width * depth.height {
+                    return Err(io::Error::new(io::ErrorKind::InvalidInput, format!(
+                        "Recording frame: depth map is {}x{} but has {} pixels", depth.width, depth.height, depth.pixels.len())));
+                }
+                Some((checked(depth.width, "depth width")?, checked(depth.height, "depth height")?))
+            },
+            None => None,
+        };
+
+        let w = &mut self.writer;
+        write_u64(w, frame.timestamp())?;
+        write_u32(w, frame_index)?;
+        write_f32(w, frame.floor_confidence())?;
+        let floor = frame.floor();
+        write_point(w, &floor.point)?;
+        write_point(w, &floor.normal)?;
+
+        write_u16(w, user_count)?;
+        for user in &users {
+            write_user(w, &user.0)?;
+        }
+
+        let mut flags = 0;
+        if user_map_size.is_some() {
+            flags |= FLAG_USER_MAP;
+        }
+        if depth_size.is_some() {
+            flags |= FLAG_DEPTH;
+        }
+        w.write_all(&[flags])?;
+
+        if let Some((width, height)) = user_map_size {
+            write_u16(w, width)?;
+            write_u16(w, height)?;
+            // At most 65535 x 65535 pixels, so counts and lengths fit a u32
+            let runs = run_lengths(user_map.pixels());
+            write_u32(w, runs.len() as u32)?;
+            for (id, length) in runs {
+                write_i16(w, id)?;
+                write_u32(w, length)?;
+            }
+        }
+
+        if let (Some(depth), Some((width, height))) = (depth, depth_size) {
+            write_u16(w, width)?;
+            write_u16(w, height)?;
+            for &px in depth.pixels {
+                write_u16(w, px)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum PlaybackSpeed {
+    /// Frames are returned at the pace they were recorded.
+    Original,
+    /// Frames are returned this many times faster than they were recorded.
+    Scaled(f32),
+    /// Frames are returned as soon as they're read.
+    Unthrottled,
+}
+
+/// Replays a recording made with `Recorder`. Once the recording ends,
+/// `read_frame` returns `Status::OutOfFlow`.
+pub struct Player<R: Read> {
+    reader: RefCell<R>,
+    speed: PlaybackSpeed,
+    started: Cell<Option<(Instant, u64)>>,
+    tracked_skeletons: RefCell<BTreeSet<NiteUserId>>,
+}
+
+impl Player<BufReader<File>> {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Player<BufReader<File>>> {
+        Player::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: Read> Player<R> {
+    pub fn new(mut reader: R) -> io::Result<Player<R>> {
+        let mut magic = [0u8; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Not a NiTE2 recording"));
+        }
+        let version = read_u16(&mut reader)?;
+        if version != VERSION {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("Unsupported recording version {}", version)));
+        }
+        Ok(Player {
+            reader: RefCell::new(reader),
+            speed: PlaybackSpeed::Original,
+            started: Cell::new(None),
+            tracked_skeletons: RefCell::new(BTreeSet::new()),
+        })
+    }
+
+    pub fn speed(&self) -> PlaybackSpeed {
+        self.speed
+    }
+
+    pub fn set_speed(&mut self, speed: PlaybackSpeed) {
+        self.speed = speed;
+        self.started.set(None);
+    }
+
+    pub fn read_frame(&self) -> Result<OwnedFrame, Status> {
+        let frame = match read_frame(&mut *self.reader.borrow_mut()) {
+            Ok(Some(frame)) => frame,
+            Ok(None) => return Err(Status::OutOfFlow),
+            Err(e) => return Err(Status::Error(format!("Couldn't read recorded frame: {}", e))),
+        };
+        self.wait_for(frame.timestamp);
+        Ok(frame)
+    }
+
+    fn wait_for(&self, timestamp: u64) {
+        let scale = match self.speed {
+            PlaybackSpeed::Original => 1.0,
+            PlaybackSpeed::Scaled(scale) if scale > 0.0 => scale as f64,
+            _ => return,
+        };
+        let (start, first_timestamp) = match self.started.get() {
+            Some(started) => started,
+            None => {
+                self.started.set(Some((Instant::now(), timestamp)));
+                return;
+            },
+        };
+        // NiTE timestamps are in microseconds
+        let offset = timestamp.saturating_sub(first_timestamp) as f64 / scale;
+        let due = start + Duration::from_micros(offset as u64);
+        let now = Instant::now();
+        if due > now {
+            ::std::thread::sleep(due - now);
+        }
+    }
+}
+
+impl<R: Read> FrameSource for Player<R> {
+    type Frame<'s> = OwnedFrame where Self: 's;
+
+    fn read_frame(&self) -> Result<OwnedFrame, Status> {
+        Player::read_frame(self)
+    }
+
+    fn track_skeleton(&self, user: NiteUserId, setting: bool) -> Result<(), Status> {
+        if setting {
+            self.tracked_skeletons.borrow_mut().insert(user);
+        } else {
+            self.tracked_skeletons.borrow_mut().remove(&user);
+        }
+        Ok(())
+    }
+
+    fn tracking_skeleton(&self, user: NiteUserId) -> bool {
+        self.tracked_skeletons.borrow().contains(&user)
+    }
+}
+
+fn checked<T: TryFrom<usize>>(value: usize, what: &str) -> io::Result<T> {
+    T::try_from(value).map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, format!("Recording frame: {} {} is too large", what, value)))
+}
+
+fn run_lengths<I: Iterator<Item = NiteUserId>>(pixels: I) -> Vec<(NiteUserId, u32)> {
+    let mut runs: Vec<(NiteUserId, u32)> = Vec::new();
+    for px in pixels {
+        if let Some(run) = runs.last_mut() {
+            if run.0 == px {
+                run.1 += 1;
+                continue;
+            }
+        }
+        runs.push((px, 1));
+    }
+    runs
+}
+
+fn read_frame<R: Read>(r: &mut R) -> io::Result<Option<OwnedFrame>> {
+    let mut timestamp = [0u8; 8];
+    match r.read(&mut timestamp[..1])? {
+        0 => return Ok(None),
+        _ => r.read_exact(&mut timestamp[1..])?,
+    }
+    let timestamp = u64::from_le_bytes(timestamp);
+    let frame_index = read_u32(r)? as usize;
+    let mut frame = OwnedFrame::new(frame_index, timestamp);
+    frame.floor_confidence = read_f32(r)?;
+    frame.floor = NitePlane {
+        point: read_point(r)?,
+        normal: read_point(r)?,
+    };
+
+    let user_count = read_u16(r)?;
+    for _ in 0..user_count {
+        frame.users.push(read_user(r)?);
+    }
+
+    let mut flags = [0u8];
+    r.read_exact(&mut flags)?;
+    let flags = flags[0];
+
+    if flags & FLAG_USER_MAP != 0 {
+        let width = read_u16(r)? as usize;
+        let height = read_u16(r)? as usize;
+        let run_count = read_u32(r)?;
+        // Runs are read before expanding them, so that nothing is allocated
+        // for pixels the recording doesn't account for.
+        let mut runs = Vec::new();
+        let mut total = 0;
+        for _ in 0..run_count {
+            let id = read_i16(r)?;
+            let length = read_u32(r)? as usize;
+            total += length;
+            if total > width * height {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "User map runs exceed its dimensions"));
+            }
+            runs.push((id, length));
+        }
+        if total != width * height {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "User map runs don't fill its dimensions"));
+        }
+        let mut pixels = Vec::with_capacity(total);
+        for (id, length) in runs {
+            pixels.extend((0..length).map(|_| id));
+        }
+        frame.user_map = OwnedUserMap::new(width, height, pixels);
+    }
+
+    if flags & FLAG_DEPTH != 0 {
+        let width = read_u16(r)? as usize;
+        let height = read_u16(r)? as usize;
+        // Read rather than allocated up front, so a corrupt size runs into
+        // the end of the file instead of allocating it.
+        let len = width * height * mem::size_of::<OniDepthPixel>();
+        let mut bytes = Vec::new();
+        r.by_ref().take(len as u64).read_to_end(&mut bytes)?;
+        if bytes.len() != len {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Depth pixels end early"));
+        }
+        let pixels = bytes.chunks(2).map(|px| u16::from_le_bytes([px[0], px[1]])).collect();
+        frame.depth = Some(OwnedDepthMap::new(width, height, pixels));
+    }
+
+    Ok(Some(frame))
+}
+
+fn write_user<W: Write>(w: &mut W, user: &NiteUserData) -> io::Result<()> {
+    write_i16(w, user.id)?;
+    write_i32(w, user.state)?;
+    write_point(w, &user.boundingBox.min)?;
+    write_point(w, &user.boundingBox.max)?;
+    write_point(w, &user.centerOfMass)?;
+    write_i32(w, user.skeleton.state)?;
+    for joint in user.skeleton.joints.iter() {
+        write_point(w, &joint.position)?;
+        write_f32(w, joint.positionConfidence)?;
+        write_f32(w, joint.orientation.x)?;
+        write_f32(w, joint.orientation.y)?;
+        write_f32(w, joint.orientation.z)?;
+        write_f32(w, joint.orientation.w)?;
+        write_f32(w, joint.orientationConfidence)?;
+    }
+    for pose in user.poses.iter() {
+        write_i32(w, pose.state)?;
+    }
+    Ok(())
+}
+
+fn read_user<R: Read>(r: &mut R) -> io::Result<UserData> {
+    let mut user: NiteUserData = unsafe { mem::zeroed() };
+    user.id = read_i16(r)?;
+    let user_state_bits = (UserState::VISIBLE | UserState::NEW | UserState::LOST).bits();
+    user.state = read_state(r, "user", user_state_bits)?;
+    user.boundingBox.min = read_point(r)?;
+    user.boundingBox.max = read_point(r)?;
+    user.centerOfMass = read_point(r)?;
+    user.skeleton.state = read_i32(r)?;
+    if !SKELETON_STATES.contains(&user.skeleton.state) {
+        return Err(invalid_state("skeleton", user.skeleton.state));
+    }
+    for (i, joint) in user.skeleton.joints.iter_mut().enumerate() {
+        joint.jointType = i as NiteJointType;
+        joint.position = read_point(r)?;
+        joint.positionConfidence = read_f32(r)?;
+        joint.orientation = NiteQuaternion {
+            x: read_f32(r)?,
+            y: read_f32(r)?,
+            z: read_f32(r)?,
+            w: read_f32(r)?,
+        };
+        joint.orientationConfidence = read_f32(r)?;
+    }
+    for (i, pose) in user.poses.iter_mut().enumerate() {
+        pose.type_ = i as NitePoseType;
+        pose.state = read_state(r, "pose", POSE_STATE_BITS)?;
+    }
+    Ok(user.into())
+}
+
+// Reads a set of state flags, none of which may be outside `bits`
+fn read_state<R: Read>(r: &mut R, what: &str, bits: i32) -> io::Result<i32> {
+    let state = read_i32(r)?;
+    if state & !bits != 0 {
+        return Err(invalid_state(what, state));
+    }
+    Ok(state)
+}
+
+fn invalid_state(what: &str, state: i32) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("Unknown {} state {}", what, state))
+}
+
+fn write_point<W: Write>(w: &mut W, point: &NitePoint3f) -> io::Result<()> {
+    write_f32(w, point.x)?;
+    write_f32(w, point.y)?;
+    write_f32(w, point.z)
+}
+
+fn read_point<R: Read>(r: &mut R) -> io::Result<NitePoint3f> {
+    Ok(NitePoint3f {
+        x: read_f32(r)?,
+        y: read_f32(r)?,
+        z: read_f32(r)?,
+    })
+}
+
+fn write_u16<W: Write>(w: &mut W, value: u16) -> io::Result<()> {
+    w.write_all(&value.to_le_bytes())
+}
+
+fn write_i16<W: Write>(w: &mut W, value: i16) -> io::Result<()> {
+    w.write_all(&value.to_le_bytes())
+}
+
+fn write_u32<W: Write>(w: &mut W, value: u32) -> io::Result<()> {
+    w.write_all(&value.to_le_bytes())
+}
+
+fn write_i32<W: Write>(w: &mut W, value: i32) -> io::Result<()> {
+    w.write_all(&value.to_le_bytes())
+}
+
+fn write_u64<W: Write>(w: &mut W, value: u64) -> io::Result<()> {
+    w.write_all(&value.to_le_bytes())
+}
+
+fn write_f32<W: Write>(w: &mut W, value: f32) -> io::Result<()> {
+    w.write_all(&value.to_le_bytes())
+}
+
+fn read_u16<R: Read>(r: &mut R) -> io::Result<u16> {
+    let mut bytes = [0u8; 2];
+    r.read_exact(&mut bytes)?;
+    Ok(u16::from_le_bytes(bytes))
+}
+
+fn read_i16<R: Read>(r: &mut R) -> io::Result<i16> {
+    let mut bytes = [0u8; 2];
+    r.read_exact(&mut bytes)?;
+    Ok(i16::from_le_bytes(bytes))
+}
+
+fn read_u32<R: Read>(r: &mut R) -> io::Result<u32> {
+    let mut bytes = [0u8; 4];
+    r.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_i32<R: Read>(r: &mut R) -> io::Result<i32> {
+    let mut bytes = [0u8; 4];
+    r.read_exact(&mut bytes)?;
+    Ok(i32::from_le_bytes(bytes))
+}
+
+fn read_f32<R: Read>(r: &mut R) -> io::Result<f32> {
+    let mut bytes = [0u8; 4];
+    r.read_exact(&mut bytes)?;
+    Ok(f32::from_le_bytes(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mock::MockUser;
+    use types::{JointType, SkeletonState, WorldPoint};
+
+    fn scripted_frame(frame_index: usize) -> OwnedFrame {
+        let mut frame = OwnedFrame::new(frame_index, 1_000 + frame_index as u64 * 33_333);
+        frame.floor_confidence = 0.75;
+        frame.floor = NitePlane {
+            point: NitePoint3f { x: 0.0, y: -900.0, z: 2000.0 },
+            normal: NitePoint3f { x: 0.0, y: 1.0, z: 0.0 },
+        };
+        frame.users.push(MockUser::new(1)
+            .skeleton_state(SkeletonState::Tracked)
+            .center_of_mass(WorldPoint::new(10.0, 20.0, 1500.0))
+            .joint(JointType::Head, WorldPoint::new(1.0, 2.0, 3.0), 0.5)
+            .into());
+        frame.users.push(MockUser::new(2).state(UserState::LOST).into());
+        frame.user_map = OwnedUserMap::new(4, 3, vec![0, 0, 1, 1, 0, 1, 1, 2, 2, 2, 0, 0]);
+        frame
+    }
+
+    #[test]
+    fn round_trip() {
+        let depth: Vec<OniDepthPixel> = (0..12).collect();
+        let mut recorder = Recorder::new(Vec::new(), true).expect("writing to a Vec");
+        for i in 0..3 {
+            let depth = if i == 1 { Some(DepthMap { pixels: &depth, width: 4, height: 3 }) } else { None };
+            recorder.record(&scripted_frame(i), depth).expect("frame fits the format");
+        }
+        let bytes = recorder.finish().expect("writing to a Vec");
+
+        let mut player = Player::new(&bytes[..]).expect("valid recording");
+        player.set_speed(PlaybackSpeed::Unthrottled);
+        for i in 0..3 {
+            let expected = scripted_frame(i);
+            let frame = player.read_frame().expect("recorded frame");
+            assert_eq!(frame.timestamp, expected.timestamp);
+            assert_eq!(frame.frame_index, i);
+            assert_eq!(frame.floor_confidence, 0.75);
+            assert_eq!(frame.floor.point.y, -900.0);
+            assert_eq!(frame.floor.normal.y, 1.0);
+            assert_eq!(frame.user_map, expected.user_map);
+            assert_eq!(frame.depth.is_some(), i == 1);
+
+            assert_eq!(frame.users.len(), 2);
+            let user = &frame.users[0];
+            assert_eq!(user.id(), 1);
+            assert!(user.is_visible());
+            assert_eq!(user.center_of_mass(), WorldPoint::new(10.0, 20.0, 1500.0));
+            let skeleton = user.skeleton().expect("tracked skeleton");
+            let head = skeleton.joint(JointType::Head);
+            assert_eq!(head.position(), WorldPoint::new(1.0, 2.0, 3.0));
+            assert_eq!(head.position_confidence(), 0.5);
+            assert!(frame.users[1].is_lost());
+        }
+        assert_eq!(player.read_frame().err(), Some(Status::OutOfFlow));
+    }
+
+    #[test]
+    fn depth_pixels_must_match_dimensions() {
+        let depth = vec![0; 11];
+        let mut recorder = Recorder::new(Vec::new(), false).expect("writing to a Vec");
+        let error = recorder.record(&scripted_frame(0), Some(DepthMap { pixels: &depth, width: 4, height: 3 }))
+            .expect_err("11 pixels aren't 4x3");
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+
+        // Nothing was written for the rejected frame
+        let bytes = recorder.finish().expect("writing to a Vec");
+        assert_eq!(bytes.len(), MAGIC.len() + 2);
+    }
+
+    #[test]
+    fn oversized_frames_are_rejected() {
+        let mut frame = scripted_frame(0);
+        frame.user_map = OwnedUserMap::new(70_000, 1, vec![0; 70_000]);
+        let mut recorder = Recorder::new(Vec::new(), true).expect("writing to a Vec");
+        let error = recorder.record(&frame, None).expect_err("width doesn't fit a u16");
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn truncated_depth_is_an_error() {
+        let depth: Vec<OniDepthPixel> = vec![0; 12];
+        let mut recorder = Recorder::new(Vec::new(), false).expect("writing to a Vec");
+        recorder.record(&scripted_frame(0), Some(DepthMap { pixels: &depth, width: 4, height: 3 })).expect("frame fits the format");
+        let mut bytes = recorder.finish().expect("writing to a Vec");
+
+        // Claim a 65535x65535 depth map in place of the 4x3 one
+        let header = bytes.len() - depth.len() * 2 - 4;
+        bytes[header..header + 4].copy_from_slice(&[0xff; 4]);
+        let player = Player::new(&bytes[..]).expect("valid header");
+        assert!(player.read_frame().is_err());
+    }
+
+    #[test]
+    fn unknown_states_are_invalid_data() {
+        let mut recorder = Recorder::new(Vec::new(), false).expect("writing to a Vec");
+        recorder.record(&scripted_frame(0), None).expect("frame fits the format");
+        let bytes = recorder.finish().expect("writing to a Vec");
+
+        // The first user starts after the header and 42 bytes of frame, with
+        // its state 2 bytes in and its skeleton state 42 bytes in.
+        let user = MAGIC.len() + 2 + 42;
+        for &(offset, state) in &[(2, 64i32), (42, 99), (42, -1)] {
+            let mut bytes = bytes.clone();
+            bytes[user + offset..user + offset + 4].copy_from_slice(&state.to_le_bytes());
+            let mut reader = &bytes[MAGIC.len() + 2..];
+            let error = read_frame(&mut reader).expect_err("corrupted state");
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        }
+    }
+}
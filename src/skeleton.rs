@@ -1,20 +1,280 @@
+use std::slice;
 use openni2::Stream;
-use nite2_sys::{NiteSkeleton, NiteSkeletonJoint, /* NiteQuaternion */};
-use types::{JointType, Status};
+use nite2_sys::{NiteSkeleton, NiteSkeletonJoint};
+use quaternion::Quaternion;
+use types::{JointType, Status, WorldPoint};
+
+#[derive(Clone, Copy, Debug)]
+#[repr(transparent)]
+pub struct Joint(NiteSkeletonJoint);
+
+impl Joint {
+    pub fn joint_type(&self) -> JointType {
+        self.0.jointType.into()
+    }
+
+    pub fn position(&self) -> WorldPoint {
+        self.0.position.into()
+    }
+
+    pub fn position_confidence(&self) -> f32 {
+        self.0.positionConfidence
+    }
+
+    pub fn orientation(&self) -> Quaternion {
+        self.0.orientation.into()
+    }
+
+    pub fn orientation_confidence(&self) -> f32 {
+        self.0.orientationConfidence
+    }
+}
+
+/// The segment from each joint to its parent in the `JointType` hierarchy,
+/// named for the part of the body it runs along.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Bone {
+    Head,
+    Spine,
+    LeftCollar,
+    RightCollar,
+    LeftUpperArm,
+    RightUpperArm,
+    LeftForearm,
+    RightForearm,
+    LeftPelvis,
+    RightPelvis,
+    LeftThigh,
+    RightThigh,
+    LeftShin,
+    RightShin,
+}
+
+impl Bone {
+    /// In the order of their child joints in `JointType::ALL`.
+    pub const ALL: [Bone; 14] = [
+        Bone::Head,
+        Bone::Spine,
+        Bone::LeftCollar,
+        Bone::RightCollar,
+        Bone::LeftUpperArm,
+        Bone::RightUpperArm,
+        Bone::LeftForearm,
+        Bone::RightForearm,
+        Bone::LeftPelvis,
+        Bone::RightPelvis,
+        Bone::LeftThigh,
+        Bone::RightThigh,
+        Bone::LeftShin,
+        Bone::RightShin,
+    ];
+
+    /// The bone ending at `joint`, or `None` for the root (`Torso`).
+    pub fn ending_at(joint: JointType) -> Option<Bone> {
+        Bone::ALL.iter().cloned().find(|bone| bone.child() == joint)
+    }
+
+    /// The joint nearer the torso.
+    pub fn parent(&self) -> JointType {
+        self.child().parent().expect("Only the root joint has no parent")
+    }
+
+    pub fn child(&self) -> JointType {
+        match *self {
+            Bone::Head => JointType::Head,
+            Bone::Spine => JointType::Neck,
+            Bone::LeftCollar => JointType::LeftShoulder,
+            Bone::RightCollar => JointType::RightShoulder,
+            Bone::LeftUpperArm => JointType::LeftElbow,
+            Bone::RightUpperArm => JointType::RightElbow,
+            Bone::LeftForearm => JointType::LeftHand,
+            Bone::RightForearm => JointType::RightHand,
+            Bone::LeftPelvis => JointType::LeftHip,
+            Bone::RightPelvis => JointType::RightHip,
+            Bone::LeftThigh => JointType::LeftKnee,
+            Bone::RightThigh => JointType::RightKnee,
+            Bone::LeftShin => JointType::LeftFoot,
+            Bone::RightShin => JointType::RightFoot,
+        }
+    }
+}
+
+/// A bone along with the two joints of a particular skeleton.
+#[derive(Clone, Copy, Debug)]
+pub struct Limb<'a> {
+    bone: Bone,
+    parent: &'a Joint,
+    child: &'a Joint,
+}
+
+impl<'a> Limb<'a> {
+    pub fn bone(&self) -> Bone {
+        self.bone
+    }
+
+    pub fn parent(&self) -> &'a Joint {
+        self.parent
+    }
+
+    pub fn child(&self) -> &'a Joint {
+        self.child
+    }
+
+    pub fn length(&self) -> f32 {
+        self.parent.position().distance(self.child.position())
+    }
+
+    /// Unit vector pointing from the parent joint to the child.
+    pub fn direction(&self) -> WorldPoint {
+        (self.child.position() - self.parent.position()).normalize()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct LocalJoint {
+    pub joint_type: JointType,
+    /// Position relative to the parent joint, in the parent's rotated frame.
+    /// For the root (`Torso`) this is its world position.
+    pub offset: WorldPoint,
+    /// Rotation relative to the parent joint. For the root this is its
+    /// global orientation.
+    pub rotation: Quaternion,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct LocalPose {
+    joints: [LocalJoint; 15],
+}
+
+impl LocalPose {
+    pub fn joint(&self, joint_type: JointType) -> &LocalJoint {
+        &self.joints[joint_type as usize]
+    }
+
+    pub fn joints(&self) -> &[LocalJoint] {
+        &self.joints
+    }
+
+    pub fn root(&self) -> &LocalJoint {
+        self.joint(JointType::Torso)
+    }
+
+    /// A T-pose (all identity rotations) with each joint's offset averaged
+    /// over `poses`.
+    pub(crate) fn rest<'a, I: IntoIterator<Item = &'a LocalPose>>(poses: I) -> Option<LocalPose> {
+        let mut rest = LocalPose {
+            joints: [LocalJoint {
+                joint_type: JointType::Torso,
+                offset: WorldPoint::new(0.0, 0.0, 0.0),
+                rotation: Quaternion::identity(),
+            }; 15],
+        };
+        for (local, &joint_type) in rest.joints.iter_mut().zip(JointType::ALL.iter()) {
+            local.joint_type = joint_type;
+        }
+        let mut count = 0;
+        for pose in poses {
+            for (local, joint) in rest.joints.iter_mut().zip(pose.joints.iter()) {
+                local.offset = local.offset + joint.offset;
+            }
+            count += 1;
+        }
+        if count == 0 {
+            return None;
+        }
+        for local in rest.joints.iter_mut() {
+            local.offset = local.offset * (1.0 / count as f32);
+        }
+        Some(rest)
+    }
+}
 
 #[derive(Clone, Copy, Debug)]
 pub struct Skeleton(pub(crate) NiteSkeleton);
 
 impl Skeleton {
-    pub fn joint(&self, joint_type: JointType) -> &NiteSkeletonJoint {
-        &self.0.joints[joint_type as usize]
+    pub fn joint(&self, joint_type: JointType) -> &Joint {
+        &self.joints()[joint_type as usize]
     }
 
-    pub fn joints(&self) -> &[NiteSkeletonJoint] {
-        &self.0.joints
+    pub fn joints(&self) -> &[Joint] {
+        // Joint is a transparent wrapper around NiteSkeletonJoint
+        unsafe { slice::from_raw_parts(self.0.joints.as_ptr() as *const Joint, self.0.joints.len()) }
+    }
+
+    /// Bones whose joints both have a position confidence of at least
+    /// `min_confidence`. NiTE reports 1.0 for tracked joints and 0.5 for
+    /// joints it is guessing at.
+    ///
+    /// Only joint-to-parent segments are bones, so unlike `limbs` this has
+    /// no shoulder to shoulder, hip to hip, or shoulder to torso lines.
+    pub fn bones(&self, min_confidence: f32) -> impl Iterator<Item = Limb<'_>> + '_ {
+        Bone::ALL.iter().map(move |&bone| self.limb(bone)).filter(move |limb| {
+            limb.parent.position_confidence() >= min_confidence
+                && limb.child.position_confidence() >= min_confidence
+        })
+    }
+
+    pub fn limb(&self, bone: Bone) -> Limb<'_> {
+        Limb {
+            bone,
+            parent: self.joint(bone.parent()),
+            child: self.joint(bone.child()),
+        }
+    }
+
+    /// Rotations and offsets of each joint relative to its parent in the
+    /// `JointType` hierarchy. In NiTE's calibration pose (a T-pose facing the
+    /// sensor) every orientation is the identity, so this can drive a rig
+    /// whose rest pose is a T-pose.
+    pub fn local_pose(&self) -> LocalPose {
+        let mut joints = [LocalJoint {
+            joint_type: JointType::Torso,
+            offset: WorldPoint::new(0.0, 0.0, 0.0),
+            rotation: Quaternion::identity(),
+        }; 15];
+        for (local, &joint_type) in joints.iter_mut().zip(JointType::ALL.iter()) {
+            let joint = self.joint(joint_type);
+            let global = self.global_orientation(joint_type);
+            *local = match joint_type.parent() {
+                Some(parent_type) => {
+                    let parent = self.joint(parent_type);
+                    let to_parent = self.global_orientation(parent_type).inverse();
+                    LocalJoint {
+                        joint_type,
+                        offset: to_parent.rotate(joint.position() - parent.position()),
+                        rotation: to_parent * global,
+                    }
+                },
+                None => LocalJoint {
+                    joint_type,
+                    offset: joint.position(),
+                    rotation: global,
+                },
+            };
+        }
+        LocalPose { joints }
+    }
+
+    // NiTE has no orientation for the hands and feet and reports them as
+    // all zeroes; those joints follow their parent instead.
+    fn global_orientation(&self, joint_type: JointType) -> Quaternion {
+        let orientation = self.joint(joint_type).orientation();
+        if orientation.norm() > 0.0 {
+            orientation.normalize()
+        } else {
+            match joint_type.parent() {
+                Some(parent) => self.global_orientation(parent),
+                None => Quaternion::identity(),
+            }
+        }
     }
 
-    pub fn limbs(&self) -> Vec<(&NiteSkeletonJoint, &NiteSkeletonJoint)> {
+    #[deprecated(note = "use `bones(1.0)`, which follows the joint hierarchy and leaves out the lines across the shoulders and hips")]
+    pub fn limbs(&self) -> Vec<(&Joint, &Joint)> {
         let head = self.joint(JointType::Head);
         let neck = self.joint(JointType::Neck);
         let left_shoulder = self.joint(JointType::LeftShoulder);
@@ -48,7 +308,7 @@ impl Skeleton {
             (right_hip, right_knee),
             (right_knee, right_foot),
         ].iter().filter_map(|&joints| {
-            if joints.0.positionConfidence >= 1.0 && joints.1.positionConfidence >= 1.0 {
+            if joints.0.position_confidence() >= 1.0 && joints.1.position_confidence() >= 1.0 {
                 Some(joints)
             } else {
                 None
@@ -84,3 +344,94 @@ impl Skeleton {
         }))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mock::MockUser;
+    use types::SkeletonState;
+    use user_tracker::UserData;
+
+    #[test]
+    fn bones_follow_the_joint_hierarchy() {
+        assert_eq!(Bone::ending_at(JointType::Torso), None);
+        for &joint in JointType::ALL.iter().filter(|&&joint| joint != JointType::Torso) {
+            let bone = Bone::ending_at(joint).expect("every other joint ends a bone");
+            assert_eq!(bone.child(), joint);
+            assert_eq!(Some(bone.parent()), joint.parent());
+        }
+        assert_eq!(Bone::ending_at(JointType::LeftKnee), Some(Bone::LeftThigh));
+        assert_eq!(Bone::LeftThigh.parent(), JointType::LeftHip);
+        assert_eq!(Bone::Spine.parent(), JointType::Torso);
+    }
+
+    #[test]
+    fn bones_skip_unconfident_joints() {
+        let mut user = MockUser::new(1).skeleton_state(SkeletonState::Tracked);
+        for &joint in JointType::ALL.iter() {
+            user = user.joint(joint, WorldPoint::new(0.0, 0.0, 0.0), 1.0);
+        }
+        let user: UserData = user
+            .joint(JointType::LeftElbow, WorldPoint::new(0.0, 0.0, 0.0), 0.5)
+            .joint(JointType::RightFoot, WorldPoint::new(0.0, 0.0, 0.0), 0.0)
+            .into();
+        let skeleton = user.skeleton().expect("tracked skeleton");
+
+        let confident: Vec<Bone> = skeleton.bones(1.0).map(|limb| limb.bone()).collect();
+        assert_eq!(confident.len(), 11);
+        for bone in &[Bone::LeftUpperArm, Bone::LeftForearm, Bone::RightShin] {
+            assert!(!confident.contains(bone));
+        }
+
+        let guessed: Vec<Bone> = skeleton.bones(0.5).map(|limb| limb.bone()).collect();
+        assert_eq!(guessed.len(), 13);
+        assert!(!guessed.contains(&Bone::RightShin));
+        assert_eq!(skeleton.bones(0.0).count(), 14);
+    }
+
+    #[test]
+    fn local_pose_composes_back_to_world_orientations() {
+        let about = |x, y, z, angle| Quaternion::from_axis_angle(WorldPoint::new(x, y, z), angle);
+        let mut user = MockUser::new(1).skeleton_state(SkeletonState::Tracked);
+        for (i, &joint) in JointType::ALL.iter().enumerate() {
+            let i = i as f32;
+            user = user.joint(joint, WorldPoint::new(i * 10.0, 100.0 - i * 5.0, 2000.0 + i), 1.0);
+            // NiTE reports all-zero orientations for the head, hands and feet
+            let orientation = if joint.children().is_empty() {
+                Quaternion::new(0.0, 0.0, 0.0, 0.0)
+            } else {
+                about(1.0, i, 0.5, 0.1 * i)
+            };
+            user = user.joint_orientation(joint, orientation.into());
+        }
+        let user: UserData = user.into();
+        let skeleton = user.skeleton().expect("tracked skeleton");
+        let pose = skeleton.local_pose();
+
+        let root = pose.root();
+        assert_eq!(root.joint_type, JointType::Torso);
+        assert_eq!(root.offset, skeleton.joint(JointType::Torso).position());
+
+        for &joint in JointType::ALL.iter() {
+            // Walk up to the root, then compose the local rotations back down
+            let mut chain = vec![joint];
+            while let Some(parent) = chain[chain.len() - 1].parent() {
+                chain.push(parent);
+            }
+            let mut rotation = Quaternion::identity();
+            let mut position = WorldPoint::new(0.0, 0.0, 0.0);
+            for &link in chain.iter().rev() {
+                let local = pose.joint(link);
+                assert_eq!(local.joint_type, link);
+                position = position + rotation.rotate(local.offset);
+                rotation = rotation * local.rotation;
+            }
+
+            let expected = skeleton.global_orientation(joint);
+            assert!(rotation.dot(expected).abs() > 0.9999, "{:?}: {:?} != {:?}", joint, rotation, expected);
+            assert!(position.distance(skeleton.joint(joint).position()) < 1e-2, "{:?}", joint);
+        }
+        // Hands follow their elbows
+        assert!(pose.joint(JointType::LeftHand).rotation.dot(Quaternion::identity()).abs() > 0.9999);
+    }
+}
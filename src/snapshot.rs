@@ -0,0 +1,234 @@
+use std::mem;
+use nite2_sys::*;
+use frame_source::{TrackerFrame, OwnedFrame};
+use quaternion::Quaternion;
+use skeleton::Skeleton;
+use types::{JointType, SkeletonState, PoseType, UserState, WorldPoint, DepthPoint};
+use user_tracker::{
+    UserData,
+    NITE_POSE_STATE_DETECTED,
+    NITE_POSE_STATE_IN_POSE,
+    NITE_POSE_STATE_ENTER,
+    NITE_POSE_STATE_EXIT,
+};
+
+// Serde can't derive for the bindgen structs, so these mirror them.
+#[cfg(feature = "serde")]
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "NitePoint3f")]
+struct NitePoint3fDef {
+    x: f32,
+    y: f32,
+    z: f32,
+}
+
+#[cfg(feature = "serde")]
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "NitePlane")]
+struct NitePlaneDef {
+    #[serde(with = "NitePoint3fDef")]
+    point: NitePoint3f,
+    #[serde(with = "NitePoint3fDef")]
+    normal: NitePoint3f,
+}
+
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct JointSnapshot {
+    pub joint_type: JointType,
+    pub position: WorldPoint,
+    pub position_confidence: f32,
+    pub orientation: Quaternion,
+    pub orientation_confidence: f32,
+}
+
+impl<'a> From<&'a NiteSkeletonJoint> for JointSnapshot {
+    fn from(joint: &'a NiteSkeletonJoint) -> JointSnapshot {
+        JointSnapshot {
+            joint_type: joint.jointType.into(),
+            position: joint.position.into(),
+            position_confidence: joint.positionConfidence,
+            orientation: joint.orientation.into(),
+            orientation_confidence: joint.orientationConfidence,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SkeletonSnapshot {
+    pub state: SkeletonState,
+    pub joints: Vec<JointSnapshot>,
+}
+
+impl<'a> From<&'a Skeleton> for SkeletonSnapshot {
+    fn from(skeleton: &'a Skeleton) -> SkeletonSnapshot {
+        nite_skeleton_snapshot(&skeleton.0)
+    }
+}
+
+fn nite_skeleton_snapshot(skeleton: &NiteSkeleton) -> SkeletonSnapshot {
+    SkeletonSnapshot {
+        state: skeleton.state.into(),
+        joints: skeleton.joints.iter().map(JointSnapshot::from).collect(),
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct PoseSnapshot {
+    pub pose_type: PoseType,
+    pub detected: bool,
+    pub held: bool,
+    pub entered: bool,
+    pub exited: bool,
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct UserSnapshot {
+    pub id: NiteUserId,
+    pub state: UserState,
+    pub center_of_mass: WorldPoint,
+    pub bounding_box: (DepthPoint, DepthPoint),
+    pub skeleton: SkeletonSnapshot,
+    pub poses: Vec<PoseSnapshot>,
+}
+
+impl<'a> From<&'a UserData> for UserSnapshot {
+    fn from(user: &'a UserData) -> UserSnapshot {
+        UserSnapshot {
+            id: user.id(),
+            state: user.state(),
+            center_of_mass: user.center_of_mass(),
+            bounding_box: user.bounding_box(),
+            skeleton: nite_skeleton_snapshot(&user.0.skeleton),
+            poses: [PoseType::Psi, PoseType::CrossedHands].iter().map(|&pose_type| {
+                let pose = user.pose(pose_type);
+                PoseSnapshot {
+                    pose_type,
+                    detected: pose.is_detected(),
+                    held: pose.is_held(),
+                    entered: pose.is_entered(),
+                    exited: pose.is_exited(),
+                }
+            }).collect(),
+        }
+    }
+}
+
+impl<'a> From<&'a UserSnapshot> for UserData {
+    fn from(snapshot: &'a UserSnapshot) -> UserData {
+        let mut user: NiteUserData = unsafe { mem::zeroed() };
+        user.id = snapshot.id;
+        user.state = snapshot.state.bits();
+        user.centerOfMass = point(snapshot.center_of_mass.x, snapshot.center_of_mass.y, snapshot.center_of_mass.z);
+        let (min, max) = snapshot.bounding_box;
+        user.boundingBox = NiteBoundingBox {
+            min: point(min.x, min.y, min.z),
+            max: point(max.x, max.y, max.z),
+        };
+        user.skeleton.state = snapshot.skeleton.state as NiteSkeletonState;
+        for (i, joint) in user.skeleton.joints.iter_mut().enumerate() {
+            joint.jointType = i as NiteJointType;
+            joint.orientation.w = 1.0;
+        }
+        for joint in &snapshot.skeleton.joints {
+            let nite_joint = &mut user.skeleton.joints[joint.joint_type as usize];
+            nite_joint.position = point(joint.position.x, joint.position.y, joint.position.z);
+            nite_joint.positionConfidence = joint.position_confidence;
+            nite_joint.orientation = joint.orientation.into();
+            nite_joint.orientationConfidence = joint.orientation_confidence;
+        }
+        for (i, pose) in user.poses.iter_mut().enumerate() {
+            pose.type_ = i as NitePoseType;
+        }
+        for pose in &snapshot.poses {
+            let flags = [
+                (pose.detected, NITE_POSE_STATE_DETECTED),
+                (pose.held, NITE_POSE_STATE_IN_POSE),
+                (pose.entered, NITE_POSE_STATE_ENTER),
+                (pose.exited, NITE_POSE_STATE_EXIT),
+            ];
+            user.poses[pose.pose_type as usize].state = flags.iter()
+                .filter(|&&(set, _)| set)
+                .fold(0, |state, &(_, flag)| state | flag);
+        }
+        user.into()
+    }
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct FrameSnapshot {
+    pub timestamp: u64,
+    pub frame_index: usize,
+    #[cfg_attr(feature = "serde", serde(with = "NitePlaneDef"))]
+    pub floor: NitePlane,
+    pub floor_confidence: f32,
+    pub users: Vec<UserSnapshot>,
+}
+
+impl FrameSnapshot {
+    pub fn from_frame<F: TrackerFrame>(frame: &F) -> FrameSnapshot {
+        FrameSnapshot {
+            timestamp: frame.timestamp(),
+            frame_index: frame.frame_index(),
+            floor: frame.floor(),
+            floor_confidence: frame.floor_confidence(),
+            users: frame.users().iter().map(UserSnapshot::from).collect(),
+        }
+    }
+}
+
+impl<'a> From<&'a FrameSnapshot> for OwnedFrame {
+    fn from(snapshot: &'a FrameSnapshot) -> OwnedFrame {
+        let mut frame = OwnedFrame::new(snapshot.frame_index, snapshot.timestamp);
+        frame.floor = snapshot.floor;
+        frame.floor_confidence = snapshot.floor_confidence;
+        frame.users = snapshot.users.iter().map(UserData::from).collect();
+        frame
+    }
+}
+
+fn point(x: f32, y: f32, z: f32) -> NitePoint3f {
+    NitePoint3f { x, y, z }
+}
+
+#[cfg(all(test, feature = "serde"))]
+mod tests {
+    use super::*;
+    use mock::MockUser;
+
+    fn frame() -> OwnedFrame {
+        let mut frame = OwnedFrame::new(7, 233_331);
+        frame.floor_confidence = 0.5;
+        frame.users.push(MockUser::new(1)
+            .state(UserState::VISIBLE)
+            .skeleton_state(SkeletonState::Tracked)
+            .joint(JointType::Head, WorldPoint::new(1.0, 2.0, 3.0), 0.75)
+            .joint_orientation(JointType::Head, Quaternion::new(0.0, 0.6, 0.0, 0.8).into())
+            .into());
+        frame
+    }
+
+    #[test]
+    fn serde_round_trip() {
+        let snapshot = FrameSnapshot::from_frame(&frame());
+        let json = ::serde_json::to_string(&snapshot).expect("snapshots serialize");
+        let restored: FrameSnapshot = ::serde_json::from_str(&json).expect("snapshots deserialize");
+        let restored = OwnedFrame::from(&restored);
+
+        assert_eq!(restored.timestamp, 233_331);
+        assert_eq!(restored.frame_index, 7);
+        assert_eq!(restored.floor_confidence, 0.5);
+        let user = &restored.users[0];
+        assert_eq!(user.id(), 1);
+        assert!(user.is_visible());
+        let skeleton = user.skeleton().expect("tracked skeleton");
+        let head = skeleton.joint(JointType::Head);
+        assert_eq!(head.position(), WorldPoint::new(1.0, 2.0, 3.0));
+        assert_eq!(head.position_confidence(), 0.75);
+        assert_eq!(head.orientation(), Quaternion::new(0.0, 0.6, 0.0, 0.8));
+    }
+}
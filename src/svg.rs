@@ -0,0 +1,190 @@
+use std::fmt::Write;
+use openni2::Stream;
+use frame_source::TrackerFrame;
+use types::{DepthPoint, Status};
+use user_tracker::UserData;
+
+const USER_COLORS: [&str; 6] = ["#ff0000", "#00ff00", "#0000ff", "#ffff00", "#ff00ff", "#00ffff"];
+
+/// Draws user tracker frames as SVG documents in depth image coordinates,
+/// one group per user. Skeletons and centers of mass are in world
+/// coordinates, so they're only drawn when a depth stream is given to
+/// project them with.
+pub struct SvgRenderer<'a> {
+    depth_stream: Option<&'a Stream<'a>>,
+    silhouettes: bool,
+    bones: bool,
+    joints: bool,
+    center_of_mass: bool,
+    bounding_boxes: bool,
+    min_confidence: f32,
+    simplify: f32,
+}
+
+impl<'a> Default for SvgRenderer<'a> {
+    fn default() -> SvgRenderer<'a> {
+        SvgRenderer::new()
+    }
+}
+
+impl<'a> SvgRenderer<'a> {
+    /// Draws everything, with bones between joints with a confidence of at
+    /// least 0.5 and silhouettes simplified to within a pixel.
+    pub fn new() -> SvgRenderer<'a> {
+        SvgRenderer {
+            depth_stream: None,
+            silhouettes: true,
+            bones: true,
+            joints: true,
+            center_of_mass: true,
+            bounding_boxes: true,
+            min_confidence: 0.5,
+            simplify: 1.0,
+        }
+    }
+
+    pub fn depth_stream(mut self, depth_stream: &'a Stream<'a>) -> SvgRenderer<'a> {
+        self.depth_stream = Some(depth_stream);
+        self
+    }
+
+    pub fn silhouettes(mut self, draw: bool) -> SvgRenderer<'a> {
+        self.silhouettes = draw;
+        self
+    }
+
+    pub fn bones(mut self, draw: bool) -> SvgRenderer<'a> {
+        self.bones = draw;
+        self
+    }
+
+    pub fn joints(mut self, draw: bool) -> SvgRenderer<'a> {
+        self.joints = draw;
+        self
+    }
+
+    pub fn center_of_mass(mut self, draw: bool) -> SvgRenderer<'a> {
+        self.center_of_mass = draw;
+        self
+    }
+
+    pub fn bounding_boxes(mut self, draw: bool) -> SvgRenderer<'a> {
+        self.bounding_boxes = draw;
+        self
+    }
+
+    /// Joints below this position confidence, and their bones, are skipped.
+    pub fn min_confidence(mut self, min_confidence: f32) -> SvgRenderer<'a> {
+        self.min_confidence = min_confidence;
+        self
+    }
+
+    /// Tolerance in pixels for simplifying silhouette outlines; 0.0 keeps
+    /// every corner.
+    pub fn simplify(mut self, epsilon: f32) -> SvgRenderer<'a> {
+        self.simplify = epsilon;
+        self
+    }
+
+    pub fn render<F: TrackerFrame>(&self, frame: &F) -> Result<String, Status> {
+        let user_map = frame.user_map();
+        let mut svg = String::new();
+        let _ = writeln!(svg, r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}">"#,
+            w = user_map.width(), h = user_map.height());
+        for user in frame.users() {
+            let color = USER_COLORS[(user.id().max(1) as usize - 1) % USER_COLORS.len()];
+            let _ = writeln!(svg, r#"  <g id="user-{}" stroke="{}" fill="{}">"#, user.id(), color, color);
+
+            if self.silhouettes {
+                let mut path = String::new();
+                for contour in user_map.contours(user.id()) {
+                    let contour = if self.simplify > 0.0 { contour.simplify(self.simplify) } else { contour };
+                    for (i, p) in contour.points().iter().enumerate() {
+                        let _ = write!(path, "{}{} {} ", if i == 0 { "M" } else { "L" }, p.x, p.y);
+                    }
+                    path.push_str("Z ");
+                }
+                if !path.is_empty() {
+                    let _ = writeln!(svg, r#"    <path class="silhouette" d="{}" fill-opacity="0.4" fill-rule="evenodd" stroke="none"/>"#, path.trim_end());
+                }
+            }
+
+            if self.bounding_boxes {
+                let (min, max) = user.bounding_box();
+                let _ = writeln!(svg, r#"    <rect class="bounding-box" x="{}" y="{}" width="{}" height="{}" fill="none"/>"#,
+                    min.x, min.y, max.x - min.x, max.y - min.y);
+            }
+
+            if let Some(depth_stream) = self.depth_stream {
+                self.render_skeleton(&mut svg, &user, depth_stream)?;
+            }
+            svg.push_str("  </g>\n");
+        }
+        svg.push_str("</svg>\n");
+        Ok(svg)
+    }
+
+    fn render_skeleton(&self, svg: &mut String, user: &UserData, depth_stream: &Stream) -> Result<(), Status> {
+        if let Ok(skeleton) = user.skeleton() {
+            let skeleton = skeleton.into_depth(depth_stream)?;
+            if self.bones {
+                for limb in skeleton.bones(self.min_confidence) {
+                    let (a, b) = (limb.parent().position(), limb.child().position());
+                    let _ = writeln!(svg, r#"    <line class="bone" x1="{}" y1="{}" x2="{}" y2="{}" stroke-width="3" stroke-linecap="round"/>"#,
+                        a.x, a.y, b.x, b.y);
+                }
+            }
+            if self.joints {
+                for joint in skeleton.joints().iter().filter(|joint| joint.position_confidence() >= self.min_confidence) {
+                    let p = joint.position();
+                    let _ = writeln!(svg, r#"    <circle class="joint" cx="{}" cy="{}" r="4" stroke="black"><title>{:?}</title></circle>"#,
+                        p.x, p.y, joint.joint_type());
+                }
+            }
+        }
+        if self.center_of_mass {
+            let DepthPoint { x, y, .. } = user.center_of_mass().into_depth(depth_stream)?;
+            let _ = writeln!(svg, r#"    <path class="center-of-mass" d="M{} {} h12 M{} {} v12" stroke-width="2"/>"#,
+                x - 6.0, y, x, y - 6.0);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use frame_source::OwnedFrame;
+    use mock::MockUser;
+    use user_map::OwnedUserMap;
+
+    #[test]
+    fn silhouettes_without_depth_stream() {
+        let mut frame = OwnedFrame::new(0, 0);
+        frame.user_map = OwnedUserMap::new(6, 4, vec![
+            0, 0, 0, 0, 2, 0,
+            0, 1, 1, 0, 2, 0,
+            0, 1, 1, 0, 2, 0,
+            0, 0, 0, 0, 0, 0,
+        ]);
+        frame.users.push(MockUser::new(1)
+            .bounding_box(DepthPoint::new(1.0, 1.0, 0.0), DepthPoint::new(3.0, 3.0, 0.0))
+            .into());
+        frame.users.push(MockUser::new(2).into());
+
+        let svg = SvgRenderer::new().render(&frame).expect("nothing to project");
+        let lines: Vec<&str> = svg.lines().map(|line| line.trim()).collect();
+        assert_eq!(lines[0], r#"<svg xmlns="http://www.w3.org/2000/svg" width="6" height="4" viewBox="0 0 6 4">"#);
+        assert_eq!(lines[1], r##"<g id="user-1" stroke="#ff0000" fill="#ff0000">"##);
+        assert_eq!(lines[2], r#"<path class="silhouette" d="M1 1 L3 1 L3 3 L1 3 Z" fill-opacity="0.4" fill-rule="evenodd" stroke="none"/>"#);
+        assert_eq!(lines[3], r#"<rect class="bounding-box" x="1" y="1" width="2" height="2" fill="none"/>"#);
+        assert_eq!(lines[lines.len() - 1], "</svg>");
+        assert_eq!(lines.iter().filter(|line| line.starts_with("<g id=")).count(), 2);
+        // Skeletons need a depth stream to be projected
+        assert!(!svg.contains("bone") && !svg.contains("joint") && !svg.contains("center-of-mass"));
+
+        let svg = SvgRenderer::new().simplify(0.0).bounding_boxes(false).render(&frame).expect("nothing to project");
+        assert!(svg.contains(r#"d="M4 0 L5 0 L5 3 L4 3 Z""#));
+        assert!(!svg.contains("bounding-box"));
+    }
+}
@@ -0,0 +1,175 @@
+use nite2_sys::NiteUserId;
+use frame_source::TrackerFrame;
+use skeleton::{Skeleton, LocalPose};
+
+// 30 fps, NiTE's usual rate, for tracks too short to measure
+const DEFAULT_INTERVAL: u64 = 33_333;
+
+#[derive(Clone, Copy, Debug)]
+pub struct TrackSample {
+    pub timestamp: u64,
+    pub frame_index: usize,
+    /// `None` when the user wasn't in the frame or their skeleton wasn't
+    /// being tracked.
+    pub skeleton: Option<Skeleton>,
+}
+
+/// One user's skeleton over a sequence of frames, for the exporters.
+#[derive(Clone, Debug)]
+pub struct SkeletonTrack {
+    user_id: NiteUserId,
+    samples: Vec<TrackSample>,
+}
+
+impl SkeletonTrack {
+    pub fn new(user_id: NiteUserId) -> SkeletonTrack {
+        SkeletonTrack {
+            user_id,
+            samples: Vec::new(),
+        }
+    }
+
+    pub fn user_id(&self) -> NiteUserId {
+        self.user_id
+    }
+
+    pub fn push_frame<F: TrackerFrame>(&mut self, frame: &F) {
+        let skeleton = frame.users().iter()
+            .find(|user| user.id() == self.user_id)
+            .and_then(|user| user.skeleton().ok());
+        self.push(frame.timestamp(), frame.frame_index(), skeleton);
+    }
+
+    /// Samples are kept in timestamp order, so one pushed out of order goes
+    /// after any others with the same or an earlier timestamp.
+    pub fn push(&mut self, timestamp: u64, frame_index: usize, skeleton: Option<Skeleton>) {
+        let index = self.samples.partition_point(|sample| sample.timestamp <= timestamp);
+        self.samples.insert(index, TrackSample { timestamp, frame_index, skeleton });
+    }
+
+    /// In timestamp order.
+    pub fn samples(&self) -> &[TrackSample] {
+        &self.samples
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    /// The samples from the first tracked skeleton to the last, inclusive.
+    pub fn tracked_span(&self) -> &[TrackSample] {
+        let first = self.samples.iter().position(|sample| sample.skeleton.is_some());
+        let last = self.samples.iter().rposition(|sample| sample.skeleton.is_some());
+        match (first, last) {
+            (Some(first), Some(last)) => &self.samples[first..=last],
+            _ => &[],
+        }
+    }
+
+    /// NiTE's calibration T-pose with the bone offsets of this user,
+    /// averaged over every tracked skeleton.
+    pub fn rest_pose(&self) -> Option<LocalPose> {
+        let poses: Vec<LocalPose> = self.samples.iter()
+            .filter_map(|sample| sample.skeleton.map(|skeleton| skeleton.local_pose()))
+            .collect();
+        LocalPose::rest(&poses)
+    }
+
+    /// The median time between samples in microseconds, which skips over
+    /// gaps from dropped frames.
+    pub fn frame_interval(&self) -> Option<u64> {
+        let mut intervals: Vec<u64> = self.samples.windows(2)
+            .map(|pair| pair[1].timestamp.saturating_sub(pair[0].timestamp))
+            .filter(|&interval| interval > 0)
+            .collect();
+        if intervals.is_empty() {
+            return None;
+        }
+        intervals.sort_unstable();
+        Some(intervals[intervals.len() / 2])
+    }
+
+    /// Samples of the tracked span at a fixed `interval` (microseconds),
+    /// each taken from the recorded sample nearest in time. The timestamps
+    /// of the returned samples are the resampled times, none of which fall
+    /// after the end of the span, so a last partial interval is dropped.
+    pub fn resample(&self, interval: u64) -> Vec<TrackSample> {
+        let span = self.tracked_span();
+        if span.is_empty() || interval == 0 {
+            return Vec::new();
+        }
+        let start = span[0].timestamp;
+        let end = span[span.len() - 1].timestamp;
+        let count = (end - start) / interval + 1;
+
+        let mut nearest = 0;
+        (0..count).map(|i| {
+            let timestamp = start + i * interval;
+            while nearest + 1 < span.len()
+                && span[nearest + 1].timestamp.abs_diff(timestamp) <= span[nearest].timestamp.abs_diff(timestamp)
+            {
+                nearest += 1;
+            }
+            TrackSample { timestamp, ..span[nearest] }
+        }).collect()
+    }
+
+    /// The tracked span resampled at `frame_interval` (or 30 fps for a
+    /// single sample), along with the interval used, as the fixed rate
+    /// exporters write it. `None` if no skeleton was tracked.
+    pub fn fixed_rate_samples(&self) -> Option<(u64, Vec<TrackSample>)> {
+        let interval = self.frame_interval().unwrap_or(DEFAULT_INTERVAL);
+        let samples = self.resample(interval);
+        if samples.is_empty() {
+            None
+        } else {
+            Some((interval, samples))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mock::MockUser;
+    use types::SkeletonState;
+    use user_tracker::UserData;
+
+    fn skeleton() -> Option<Skeleton> {
+        UserData::from(MockUser::new(1).skeleton_state(SkeletonState::Tracked)).skeleton().ok()
+    }
+
+    #[test]
+    fn samples_stay_in_order() {
+        let mut track = SkeletonTrack::new(1);
+        track.push(66_666, 2, skeleton());
+        track.push(0, 0, skeleton());
+        track.push(33_333, 1, None);
+        track.push(33_333, 3, skeleton());
+        let frames: Vec<usize> = track.samples().iter().map(|sample| sample.frame_index).collect();
+        assert_eq!(frames, vec![0, 1, 3, 2]);
+        assert_eq!(track.frame_interval(), Some(33_333));
+    }
+
+    #[test]
+    fn fixed_rate_samples() {
+        let mut track = SkeletonTrack::new(1);
+        assert!(track.fixed_rate_samples().is_none());
+
+        track.push(1_000, 0, None);
+        track.push(2_000, 1, skeleton());
+        let (interval, samples) = track.fixed_rate_samples().expect("one tracked sample");
+        assert_eq!(interval, 1_000);
+        assert_eq!(samples.len(), 1);
+        assert_eq!(samples[0].frame_index, 1);
+
+        track.push(4_000, 3, None);
+        track.push(5_000, 4, skeleton());
+        track.push(500, 5, skeleton());
+        let (interval, samples) = track.fixed_rate_samples().expect("tracked samples");
+        assert_eq!(interval, 1_000);
+        let times: Vec<u64> = samples.iter().map(|sample| sample.timestamp).collect();
+        assert_eq!(times, vec![500, 1_500, 2_500, 3_500, 4_500]);
+        assert_eq!(samples[4].frame_index, 4);
+    }
+}
@@ -0,0 +1,176 @@
+use std::fs::File;
+use std::io::{self, Write, BufWriter};
+use std::path::Path;
+use frame_source::TrackerFrame;
+use track::SkeletonTrack;
+use types::JointType;
+
+/// Writes the joints of every tracked user as CSV, one row per joint per
+/// frame. Positions are NiTE world coordinates in millimeters and
+/// timestamps are in microseconds.
+pub struct CsvWriter<W: Write> {
+    writer: W,
+}
+
+impl CsvWriter<BufWriter<File>> {
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<CsvWriter<BufWriter<File>>> {
+        CsvWriter::new(BufWriter::new(File::create(path)?))
+    }
+}
+
+impl<W: Write> CsvWriter<W> {
+    pub fn new(mut writer: W) -> io::Result<CsvWriter<W>> {
+        writeln!(writer, "timestamp,frame_index,user_id,joint,x,y,z,position_confidence,qx,qy,qz,qw,orientation_confidence")?;
+        Ok(CsvWriter { writer })
+    }
+
+    pub fn write_frame<F: TrackerFrame>(&mut self, frame: &F) -> io::Result<()> {
+        for user in frame.users() {
+            let skeleton = match user.skeleton() {
+                Ok(skeleton) => skeleton,
+                Err(_) => continue,
+            };
+            for joint in skeleton.joints() {
+                let position = joint.position();
+                let orientation = joint.orientation();
+                writeln!(self.writer, "{},{},{},{:?},{},{},{},{},{},{},{},{},{}",
+                    frame.timestamp(), frame.frame_index(), user.id(), joint.joint_type(),
+                    position.x, position.y, position.z, joint.position_confidence(),
+                    orientation.x, orientation.y, orientation.z, orientation.w, joint.orientation_confidence(),
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// Writes a track as an OpenSim TRC marker file with one marker per
+/// `JointType`, in millimeters, at a fixed rate of one frame every
+/// `SkeletonTrack::frame_interval`. Markers are left empty in frames where
+/// the skeleton isn't tracked or NiTE has no confidence in the joint.
+pub fn write_trc<W: Write>(writer: &mut W, track: &SkeletonTrack) -> io::Result<()> {
+    let file_name = format!("user_{}.trc", track.user_id());
+    write_trc_named(writer, track, &file_name)
+}
+
+pub fn save_trc<P: AsRef<Path>>(path: P, track: &SkeletonTrack) -> io::Result<()> {
+    let file_name = path.as_ref().file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let mut writer = BufWriter::new(File::create(&path)?);
+    write_trc_named(&mut writer, track, &file_name)?;
+    writer.flush()
+}
+
+fn write_trc_named<W: Write>(writer: &mut W, track: &SkeletonTrack, file_name: &str) -> io::Result<()> {
+    let (interval, samples) = match track.fixed_rate_samples() {
+        Some(resampled) => resampled,
+        None => return Err(io::Error::new(io::ErrorKind::InvalidInput, "Writing TRC: track has no tracked skeletons")),
+    };
+    let rate = 1_000_000.0 / interval as f64;
+    let start = samples[0].timestamp;
+
+    writeln!(writer, "PathFileType\t4\t(X/Y/Z)\t{}", file_name)?;
+    writeln!(writer, "DataRate\tCameraRate\tNumFrames\tNumMarkers\tUnits\tOrigDataRate\tOrigDataStartFrame\tOrigNumFrames")?;
+    writeln!(writer, "{:.2}\t{:.2}\t{}\t{}\tmm\t{:.2}\t1\t{}",
+        rate, rate, samples.len(), JointType::ALL.len(), rate, samples.len())?;
+
+    write!(writer, "Frame#\tTime")?;
+    for joint_type in JointType::ALL.iter() {
+        write!(writer, "\t{:?}\t\t", joint_type)?;
+    }
+    write!(writer, "\n\t")?;
+    for i in 1..=JointType::ALL.len() {
+        write!(writer, "\tX{}\tY{}\tZ{}", i, i, i)?;
+    }
+    writeln!(writer, "\n")?;
+
+    for (i, sample) in samples.iter().enumerate() {
+        write!(writer, "{}\t{:.6}", i + 1, (sample.timestamp - start) as f64 / 1_000_000.0)?;
+        for &joint_type in JointType::ALL.iter() {
+            match sample.skeleton.map(|skeleton| *skeleton.joint(joint_type)) {
+                Some(ref joint) if joint.position_confidence() > 0.0 => {
+                    let position = joint.position();
+                    write!(writer, "\t{:.4}\t{:.4}\t{:.4}", position.x, position.y, position.z)?;
+                },
+                _ => write!(writer, "\t\t\t")?,
+            }
+        }
+        writeln!(writer)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use frame_source::{FrameSource, OwnedFrame};
+    use mock::{MockFrameSource, MockUser};
+    use types::{SkeletonState, WorldPoint};
+    use user_tracker::UserData;
+
+    fn tracked_user(head_confidence: f32) -> MockUser {
+        MockUser::new(1)
+            .skeleton_state(SkeletonState::Tracked)
+            .joint(JointType::Head, WorldPoint::new(1.0, 2.0, 3.0), head_confidence)
+    }
+
+    #[test]
+    fn csv_rows() {
+        let mut frames = Vec::new();
+        for i in 0..2 {
+            let mut frame = OwnedFrame::new(i, i as u64 * 33_333);
+            frame.users.push(tracked_user(1.0).into());
+            frame.users.push(MockUser::new(2).into());
+            frames.push(frame);
+        }
+        let source = MockFrameSource::new(frames);
+        let mut csv = CsvWriter::new(Vec::new()).expect("writing to a Vec");
+        while let Ok(frame) = source.read_frame() {
+            csv.write_frame(&frame).expect("writing to a Vec");
+        }
+        let csv = String::from_utf8(csv.finish().expect("writing to a Vec")).expect("CSV is text");
+        let lines: Vec<&str> = csv.lines().collect();
+
+        assert_eq!(lines[0], "timestamp,frame_index,user_id,joint,x,y,z,position_confidence,qx,qy,qz,qw,orientation_confidence");
+        // User 2 has no skeleton, so only user 1's joints are written
+        assert_eq!(lines.len(), 1 + 2 * JointType::ALL.len());
+        assert_eq!(lines[1], "0,0,1,Head,1,2,3,1,0,0,0,1,1");
+        assert_eq!(lines[2], "0,0,1,Neck,0,0,0,0,0,0,0,1,0");
+        assert!(lines[1 + JointType::ALL.len()].starts_with("33333,1,1,Head,"));
+        assert!(lines.iter().skip(1).all(|line| line.split(',').count() == 13));
+    }
+
+    #[test]
+    fn trc_layout() {
+        let mut track = SkeletonTrack::new(1);
+        track.push(0, 0, UserData::from(tracked_user(1.0)).skeleton().ok());
+        track.push(33_333, 1, UserData::from(tracked_user(0.0)).skeleton().ok());
+
+        let mut bytes = Vec::new();
+        write_trc(&mut bytes, &track).expect("writing to a Vec");
+        let trc = String::from_utf8(bytes).expect("TRC is text");
+        let lines: Vec<&str> = trc.lines().collect();
+
+        assert_eq!(lines[0], "PathFileType\t4\t(X/Y/Z)\tuser_1.trc");
+        assert_eq!(lines[2], "30.00\t30.00\t2\t15\tmm\t30.00\t1\t2");
+        assert!(lines[3].starts_with("Frame#\tTime\tHead\t\t\tNeck\t\t\tLeftShoulder"));
+        assert!(lines[4].starts_with("\t\tX1\tY1\tZ1\tX2\tY2\tZ2"));
+        assert!(lines[4].ends_with("\tX15\tY15\tZ15"));
+        assert_eq!(lines[5], "");
+
+        let rows = &lines[6..];
+        assert_eq!(rows.len(), 2);
+        for row in rows {
+            assert_eq!(row.split('\t').count(), 2 + 3 * JointType::ALL.len());
+        }
+        assert!(rows[0].starts_with("1\t0.000000\t1.0000\t2.0000\t3.0000\t\t\t\t"));
+        // No confidence in any joint in the second frame
+        assert_eq!(rows[1], format!("2\t0.033333{}", "\t".repeat(3 * JointType::ALL.len())));
+    }
+}
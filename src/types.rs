@@ -1,4 +1,6 @@
 use std::fmt;
+use std::ops::{Add, Sub, Mul, Neg, BitOr, BitOrAssign};
+use std::os::raw::c_int;
 use nite2_sys::*;
 use openni2::{Stream, Status as OpenNI2Status};
 
@@ -62,6 +64,7 @@ impl From<OpenNI2Status> for Status {
 }
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[repr(i32)]
 pub enum JointType {
     Head = NITE_JOINT_HEAD,
@@ -102,6 +105,65 @@ impl JointType {
             _ => panic!("Unknown joint type {}", i),
         }
     }
+
+    pub const ALL: [JointType; 15] = [
+        JointType::Head,
+        JointType::Neck,
+        JointType::LeftShoulder,
+        JointType::RightShoulder,
+        JointType::LeftElbow,
+        JointType::RightElbow,
+        JointType::LeftHand,
+        JointType::RightHand,
+        JointType::Torso,
+        JointType::LeftHip,
+        JointType::RightHip,
+        JointType::LeftKnee,
+        JointType::RightKnee,
+        JointType::LeftFoot,
+        JointType::RightFoot,
+    ];
+
+    /// The next joint towards the torso, which is the root of the hierarchy.
+    pub fn parent(&self) -> Option<JointType> {
+        match *self {
+            JointType::Torso => None,
+            JointType::Neck => Some(JointType::Torso),
+            JointType::Head => Some(JointType::Neck),
+            JointType::LeftShoulder => Some(JointType::Neck),
+            JointType::RightShoulder => Some(JointType::Neck),
+            JointType::LeftElbow => Some(JointType::LeftShoulder),
+            JointType::RightElbow => Some(JointType::RightShoulder),
+            JointType::LeftHand => Some(JointType::LeftElbow),
+            JointType::RightHand => Some(JointType::RightElbow),
+            JointType::LeftHip => Some(JointType::Torso),
+            JointType::RightHip => Some(JointType::Torso),
+            JointType::LeftKnee => Some(JointType::LeftHip),
+            JointType::RightKnee => Some(JointType::RightHip),
+            JointType::LeftFoot => Some(JointType::LeftKnee),
+            JointType::RightFoot => Some(JointType::RightKnee),
+        }
+    }
+
+    pub fn children(&self) -> &'static [JointType] {
+        match *self {
+            JointType::Torso => &[JointType::Neck, JointType::LeftHip, JointType::RightHip],
+            JointType::Neck => &[JointType::Head, JointType::LeftShoulder, JointType::RightShoulder],
+            JointType::LeftShoulder => &[JointType::LeftElbow],
+            JointType::RightShoulder => &[JointType::RightElbow],
+            JointType::LeftElbow => &[JointType::LeftHand],
+            JointType::RightElbow => &[JointType::RightHand],
+            JointType::LeftHip => &[JointType::LeftKnee],
+            JointType::RightHip => &[JointType::RightKnee],
+            JointType::LeftKnee => &[JointType::LeftFoot],
+            JointType::RightKnee => &[JointType::RightFoot],
+            JointType::Head
+            | JointType::LeftHand
+            | JointType::RightHand
+            | JointType::LeftFoot
+            | JointType::RightFoot => &[],
+        }
+    }
 }
 
 impl From<NiteJointType> for JointType {
@@ -111,6 +173,7 @@ impl From<NiteJointType> for JointType {
 }
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[repr(i32)]
 pub enum SkeletonState {
     None = NITE_SKELETON_NONE,
@@ -146,6 +209,44 @@ impl From<NiteSkeletonState> for SkeletonState {
 }
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct UserState(c_int);
+
+impl UserState {
+    pub const VISIBLE: UserState = UserState(1);
+    pub const NEW: UserState = UserState(2);
+    pub const LOST: UserState = UserState(4);
+
+    pub fn bits(&self) -> c_int {
+        self.0
+    }
+
+    pub fn contains(&self, other: UserState) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl From<c_int> for UserState {
+    fn from(i: c_int) -> UserState {
+        UserState(i)
+    }
+}
+
+impl BitOr for UserState {
+    type Output = UserState;
+    fn bitor(self, other: UserState) -> UserState {
+        UserState(self.0 | other.0)
+    }
+}
+
+impl BitOrAssign for UserState {
+    fn bitor_assign(&mut self, other: UserState) {
+        self.0 |= other.0;
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[repr(i32)]
 pub enum PoseType {
     Psi = NITE_POSE_PSI,
@@ -162,7 +263,14 @@ impl PoseType {
     }
 }
 
+impl From<NitePoseType> for PoseType {
+    fn from(i: NitePoseType) -> PoseType {
+        PoseType::from_int(i)
+    }
+}
+
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[repr(i32)]
 pub enum GestureType {
     Wave = NITE_GESTURE_WAVE,
@@ -187,7 +295,97 @@ impl From<NiteGestureType> for GestureType {
     }
 }
 
+// WorldPoint and DepthPoint share their arithmetic, but are kept as
+// distinct types so the two coordinate spaces can't be mixed up.
+macro_rules! impl_point_ops {
+    ($point:ident) => {
+        impl $point {
+            pub fn new(x: f32, y: f32, z: f32) -> $point {
+                $point { x, y, z }
+            }
+
+            pub fn dot(&self, other: $point) -> f32 {
+                self.x * other.x + self.y * other.y + self.z * other.z
+            }
+
+            pub fn cross(&self, other: $point) -> $point {
+                $point {
+                    x: self.y * other.z - self.z * other.y,
+                    y: self.z * other.x - self.x * other.z,
+                    z: self.x * other.y - self.y * other.x,
+                }
+            }
+
+            pub fn length(&self) -> f32 {
+                self.dot(*self).sqrt()
+            }
+
+            pub fn distance(&self, other: $point) -> f32 {
+                (*self - other).length()
+            }
+
+            /// Scales to a length of 1.0. A zero-length point is returned as is.
+            pub fn normalize(&self) -> $point {
+                let length = self.length();
+                if length == 0.0 {
+                    *self
+                } else {
+                    *self * (1.0 / length)
+                }
+            }
+
+            pub fn lerp(&self, other: $point, t: f32) -> $point {
+                *self + (other - *self) * t
+            }
+
+            /// The angle in radians between the two points as vectors from
+            /// the origin, or 0.0 if either has zero length.
+            pub fn angle_between(&self, other: $point) -> f32 {
+                let lengths = self.length() * other.length();
+                if lengths == 0.0 {
+                    0.0
+                } else {
+                    (self.dot(other) / lengths).clamp(-1.0, 1.0).acos()
+                }
+            }
+        }
+
+        impl Add for $point {
+            type Output = $point;
+
+            fn add(self, rhs: $point) -> $point {
+                $point { x: self.x + rhs.x, y: self.y + rhs.y, z: self.z + rhs.z }
+            }
+        }
+
+        impl Sub for $point {
+            type Output = $point;
+
+            fn sub(self, rhs: $point) -> $point {
+                $point { x: self.x - rhs.x, y: self.y - rhs.y, z: self.z - rhs.z }
+            }
+        }
+
+        impl Mul<f32> for $point {
+            type Output = $point;
+
+            fn mul(self, rhs: f32) -> $point {
+                $point { x: self.x * rhs, y: self.y * rhs, z: self.z * rhs }
+            }
+        }
+
+        impl Neg for $point {
+            type Output = $point;
+
+            fn neg(self) -> $point {
+                $point { x: -self.x, y: -self.y, z: -self.z }
+            }
+        }
+    }
+}
+
 #[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct WorldPoint {
     pub x: f32,
     pub y: f32,
@@ -199,8 +397,14 @@ impl WorldPoint {
         let (x, y, z) = stream.world_to_depth((self.x, self.y, self.z))?;
         Ok(DepthPoint { x, y, z })
     }
+
+    pub fn project_onto_plane(&self, plane: &Plane) -> WorldPoint {
+        plane.project(*self)
+    }
 }
 
+impl_point_ops!(WorldPoint);
+
 impl Into<WorldPoint> for NitePoint3f {
     fn into(self) -> WorldPoint {
         WorldPoint { x: self.x, y: self.y, z: self.z }
@@ -208,6 +412,7 @@ impl Into<WorldPoint> for NitePoint3f {
 }
 
 #[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct DepthPoint {
     pub x: f32,
     pub y: f32,
@@ -221,8 +426,109 @@ impl DepthPoint {
     }
 }
 
+impl_point_ops!(DepthPoint);
+
 impl Into<DepthPoint> for NitePoint3f {
     fn into(self) -> DepthPoint {
         DepthPoint { x: self.x, y: self.y, z: self.z }
     }
 }
+
+/// A plane in world coordinates, such as the floor from
+/// `UserTrackerFrame::floor`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Plane {
+    pub point: WorldPoint,
+    pub normal: WorldPoint,
+}
+
+impl Plane {
+    /// Signed distance from the plane, positive on the side its normal
+    /// points towards.
+    pub fn distance_to(&self, point: WorldPoint) -> f32 {
+        (point - self.point).dot(self.normal.normalize())
+    }
+
+    pub fn project(&self, point: WorldPoint) -> WorldPoint {
+        point - self.normal.normalize() * self.distance_to(point)
+    }
+}
+
+impl From<NitePlane> for Plane {
+    fn from(plane: NitePlane) -> Plane {
+        Plane {
+            point: plane.point.into(),
+            normal: plane.normal.into(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn user_state_flags() {
+        let mut state = UserState::NEW | UserState::VISIBLE;
+        assert_eq!(state.bits(), 3);
+        assert!(state.contains(UserState::NEW));
+        assert!(!state.contains(UserState::LOST));
+        state |= UserState::LOST;
+        assert!(state.contains(UserState::NEW | UserState::LOST));
+    }
+
+    #[test]
+    fn cross_product() {
+        let x = WorldPoint::new(1.0, 0.0, 0.0);
+        let y = WorldPoint::new(0.0, 1.0, 0.0);
+        assert_eq!(x.cross(y), WorldPoint::new(0.0, 0.0, 1.0));
+        assert_eq!(y.cross(x), WorldPoint::new(0.0, 0.0, -1.0));
+        assert_eq!(x.cross(x), WorldPoint::new(0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn angle_between() {
+        let x = WorldPoint::new(2.0, 0.0, 0.0);
+        assert!((x.angle_between(WorldPoint::new(0.0, 3.0, 0.0)) - ::std::f32::consts::FRAC_PI_2).abs() < 1e-6);
+        assert!((x.angle_between(WorldPoint::new(-1.0, 0.0, 0.0)) - ::std::f32::consts::PI).abs() < 1e-6);
+        assert_eq!(x.angle_between(x), 0.0);
+        assert_eq!(x.angle_between(WorldPoint::new(0.0, 0.0, 0.0)), 0.0);
+    }
+
+    #[test]
+    fn normalize() {
+        assert_eq!(WorldPoint::new(0.0, 3.0, 4.0).normalize(), WorldPoint::new(0.0, 0.6, 0.8));
+        assert_eq!(WorldPoint::new(0.0, 0.0, 0.0).normalize(), WorldPoint::new(0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn plane() {
+        // The floor 900mm down, with an unnormalized normal
+        let floor = Plane {
+            point: WorldPoint::new(0.0, -900.0, 2000.0),
+            normal: WorldPoint::new(0.0, 2.0, 0.0),
+        };
+        let head = WorldPoint::new(100.0, 800.0, 1500.0);
+        assert_eq!(floor.distance_to(head), 1700.0);
+        assert_eq!(floor.distance_to(WorldPoint::new(0.0, -1000.0, 0.0)), -100.0);
+        assert_eq!(floor.project(head), WorldPoint::new(100.0, -900.0, 1500.0));
+        assert_eq!(head.project_onto_plane(&floor), floor.project(head));
+    }
+
+    #[test]
+    fn joint_hierarchy() {
+        let roots: Vec<JointType> = JointType::ALL.iter().cloned().filter(|joint| joint.parent().is_none()).collect();
+        assert_eq!(roots, vec![JointType::Torso]);
+        for &joint in JointType::ALL.iter() {
+            if let Some(parent) = joint.parent() {
+                assert!(parent.children().contains(&joint), "{:?} isn't a child of {:?}", joint, parent);
+            }
+            for child in joint.children() {
+                assert_eq!(child.parent(), Some(joint));
+            }
+        }
+        let child_count: usize = JointType::ALL.iter().map(|joint| joint.children().len()).sum();
+        assert_eq!(child_count, JointType::ALL.len() - 1);
+    }
+}
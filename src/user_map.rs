@@ -0,0 +1,297 @@
+use std::mem;
+use nite2_sys::NiteUserId;
+use contour::Contour;
+use mask::{Mask, UserRegion, RegionAccumulator};
+
+const PIXEL_SIZE: usize = mem::size_of::<NiteUserId>();
+
+/// The user id of every depth pixel, where 0 is the background. Rows may be
+/// padded past `width`, so pixels are read through `row`, `rows`, `get` or
+/// `pixels`, all of which skip the padding.
+#[derive(Clone, Copy, Debug)]
+pub struct UserMap<'a> {
+    pixels: &'a [NiteUserId],
+    width: usize,
+    height: usize,
+    stride: usize,
+}
+
+impl<'a> UserMap<'a> {
+    /// `stride` is the length of a row in bytes, like `NiteUserMap::stride`.
+    /// The last row doesn't need its padding.
+    pub fn new(pixels: &'a [NiteUserId], width: usize, height: usize, stride: usize) -> UserMap<'a> {
+        assert!(stride % PIXEL_SIZE == 0 && stride >= width * PIXEL_SIZE, "Creating UserMap: stride of {} bytes doesn't fit rows of {} pixels", stride, width);
+        let len = UserMap::required_len(width, height, stride);
+        assert!(pixels.len() >= len, "Creating UserMap: expected at least {} pixels, got {}", len, pixels.len());
+        UserMap {
+            pixels: &pixels[..len],
+            width,
+            height,
+            stride,
+        }
+    }
+
+    pub(crate) fn required_len(width: usize, height: usize, stride: usize) -> usize {
+        if height == 0 {
+            0
+        } else {
+            (height - 1) * (stride / PIXEL_SIZE) + width
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Length of a row in bytes, including padding.
+    pub fn stride(&self) -> usize {
+        self.stride
+    }
+
+    /// Row `y` without its padding. Panics if `y` is out of bounds.
+    pub fn row(&self, y: usize) -> &'a [NiteUserId] {
+        assert!(y < self.height, "UserMap row {} out of bounds (height {})", y, self.height);
+        let start = y * (self.stride / PIXEL_SIZE);
+        &self.pixels[start..start + self.width]
+    }
+
+    pub fn rows(&self) -> impl DoubleEndedIterator<Item = &'a [NiteUserId]> + ExactSizeIterator + 'a {
+        let map = *self;
+        (0..self.height).map(move |y| map.row(y))
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> Option<NiteUserId> {
+        if x < self.width && y < self.height {
+            Some(self.row(y)[x])
+        } else {
+            None
+        }
+    }
+
+    /// Every pixel, row by row.
+    pub fn pixels(&self) -> impl Iterator<Item = NiteUserId> + 'a {
+        self.rows().flat_map(|row| row.iter().cloned())
+    }
+
+    /// The pixels belonging to `id`. An id of 0 gives the background.
+    pub fn mask(&self, id: NiteUserId) -> Mask {
+        let pixels = self.pixels().map(|px| px == id).collect();
+        Mask::from_pixels(self.width, self.height, pixels)
+    }
+
+    /// Outlines of the user's pixels; see `Mask::contours`.
+    pub fn contours(&self, id: NiteUserId) -> Vec<Contour> {
+        self.mask(id).contours()
+    }
+
+    /// Pixel count, centroid and bounds of every user in the map, ordered
+    /// by id, from a single pass over the pixels.
+    pub fn regions(&self) -> Vec<UserRegion> {
+        let mut accumulators: Vec<Option<RegionAccumulator>> = Vec::new();
+        for (y, row) in self.rows().enumerate() {
+            for (x, &id) in row.iter().enumerate() {
+                if id <= 0 {
+                    continue;
+                }
+                let index = id as usize;
+                if index >= accumulators.len() {
+                    accumulators.resize(index + 1, None);
+                }
+                accumulators[index].get_or_insert_with(|| RegionAccumulator::new(x, y)).add(x, y);
+            }
+        }
+        accumulators.iter().enumerate()
+            .filter_map(|(id, accumulator)| accumulator.map(|accumulator| accumulator.finish(id as NiteUserId)))
+            .collect()
+    }
+
+    /// Runs `filter` over each user's mask, such as
+    /// `|mask| mask.open(1).remove_small_blobs(100)`, and combines the
+    /// results into a new map. Where filtered masks overlap, the pixel goes
+    /// to the user it belonged to before, or else the lowest id.
+    pub fn filter_masks<F: Fn(&Mask) -> Mask>(&self, filter: F) -> OwnedUserMap {
+        let original = self.to_packed();
+        let mut pixels = vec![0; self.width * self.height];
+        for id in self.user_ids() {
+            let mask = filter(&self.mask(id));
+            assert!(mask.width() == self.width && mask.height() == self.height, "Filtering UserMap: mask changed size");
+            for ((px, &set), &before) in pixels.iter_mut().zip(mask.pixels()).zip(original.pixels()) {
+                if set && (*px == 0 || before == id) {
+                    *px = id;
+                }
+            }
+        }
+        OwnedUserMap::new(self.width, self.height, pixels)
+    }
+
+    pub fn user_ids(&self) -> Vec<NiteUserId> {
+        self.regions().iter().map(|region| region.id()).collect()
+    }
+
+    /// A copy without row padding.
+    pub fn to_packed(&self) -> OwnedUserMap {
+        let mut pixels = Vec::with_capacity(self.width * self.height);
+        for row in self.rows() {
+            pixels.extend_from_slice(row);
+        }
+        OwnedUserMap::new(self.width, self.height, pixels)
+    }
+}
+
+/// A user map that owns its pixels, row by row with no padding.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OwnedUserMap {
+    pixels: Vec<NiteUserId>,
+    width: usize,
+    height: usize,
+}
+
+impl OwnedUserMap {
+    pub fn new(width: usize, height: usize, pixels: Vec<NiteUserId>) -> OwnedUserMap {
+        assert_eq!(pixels.len(), width * height, "Creating OwnedUserMap: expected {} pixels, got {}", width * height, pixels.len());
+        OwnedUserMap { pixels, width, height }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn pixels(&self) -> &[NiteUserId] {
+        &self.pixels
+    }
+
+    pub fn pixels_mut(&mut self) -> &mut [NiteUserId] {
+        &mut self.pixels
+    }
+
+    pub fn as_user_map(&self) -> UserMap<'_> {
+        UserMap {
+            pixels: &self.pixels,
+            width: self.width,
+            height: self.height,
+            stride: self.width * PIXEL_SIZE,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mask::PixelBounds;
+
+    // 3x3 pixels in rows of 5, padded with 9s except after the last row
+    const PADDED: [NiteUserId; 13] = [1, 2, 3, 9, 9, 4, 5, 6, 9, 9, 7, 8, 0];
+
+    #[test]
+    fn padding_is_skipped() {
+        let map = UserMap::new(&PADDED, 3, 3, 5 * PIXEL_SIZE);
+        assert_eq!(map.stride(), 10);
+        assert_eq!(map.row(1), &[4, 5, 6]);
+        assert_eq!(map.row(2), &[7, 8, 0]);
+        assert_eq!(map.rows().len(), 3);
+        assert_eq!(map.rows().next_back(), Some(&[7, 8, 0][..]));
+        assert_eq!(map.get(2, 1), Some(6));
+        assert_eq!(map.get(3, 0), None);
+        assert_eq!(map.get(0, 3), None);
+        assert_eq!(map.pixels().collect::<Vec<_>>(), vec![1, 2, 3, 4, 5, 6, 7, 8, 0]);
+        assert_eq!(map.to_packed(), OwnedUserMap::new(3, 3, vec![1, 2, 3, 4, 5, 6, 7, 8, 0]));
+    }
+
+    #[test]
+    fn last_row_needs_no_padding() {
+        assert_eq!(UserMap::required_len(3, 3, 10), 13);
+        assert_eq!(UserMap::required_len(3, 1, 10), 3);
+        assert_eq!(UserMap::required_len(3, 0, 10), 0);
+        // Extra pixels past the last row are ignored
+        let mut pixels = PADDED.to_vec();
+        pixels.extend_from_slice(&[9, 9]);
+        assert_eq!(UserMap::new(&pixels, 3, 3, 10).pixels().count(), 9);
+    }
+
+    #[test]
+    #[should_panic(expected = "stride")]
+    fn odd_stride_panics() {
+        UserMap::new(&PADDED, 3, 3, 7);
+    }
+
+    #[test]
+    #[should_panic(expected = "at least 13 pixels")]
+    fn short_slice_panics() {
+        UserMap::new(&PADDED[..12], 3, 3, 10);
+    }
+
+    // Users 1 and 3 with no user 2, and a pixel NiTE never hands out
+    fn two_users() -> OwnedUserMap {
+        OwnedUserMap::new(4, 3, vec![
+            3, 3, 0, 1,
+            3, -1, 0, 1,
+            0, 0, 0, 1,
+        ])
+    }
+
+    #[test]
+    fn regions_by_id() {
+        let map = two_users();
+        let map = map.as_user_map();
+        assert_eq!(map.user_ids(), vec![1, 3]);
+
+        let regions = map.regions();
+        assert_eq!(regions.len(), 2);
+        assert_eq!(regions[0].id(), 1);
+        assert_eq!(regions[0].pixel_count(), 3);
+        assert_eq!(regions[0].centroid(), (3.0, 1.0));
+        assert_eq!(regions[0].bounds(), PixelBounds { min_x: 3, min_y: 0, max_x: 3, max_y: 2 });
+        assert_eq!(regions[1].id(), 3);
+        assert_eq!(regions[1].pixel_count(), 3);
+        assert_eq!(regions[1].centroid(), (1.0 / 3.0, 1.0 / 3.0));
+        assert_eq!(regions[1].bounds(), PixelBounds { min_x: 0, min_y: 0, max_x: 1, max_y: 1 });
+    }
+
+    #[test]
+    fn masks() {
+        let map = two_users();
+        let map = map.as_user_map();
+        let user = map.mask(3);
+        assert_eq!(user.count(), 3);
+        assert!(user.get(0, 1) && !user.get(1, 1));
+        assert_eq!(user.to_user_map(3).pixels(), &[3, 3, 0, 0, 3, 0, 0, 0, 0, 0, 0, 0]);
+
+        let background = map.mask(0);
+        assert_eq!(background.count(), 5);
+        assert!(background.get(2, 0) && !background.get(1, 1));
+        assert!(map.mask(2).is_empty());
+    }
+
+    #[test]
+    fn strided_map_matches_its_packed_copy() {
+        let packed = two_users();
+        let mut pixels = Vec::new();
+        for row in packed.pixels().chunks(4) {
+            pixels.extend_from_slice(row);
+            pixels.extend_from_slice(&[2, 2]);
+        }
+        let strided = UserMap::new(&pixels, 4, 3, 6 * PIXEL_SIZE);
+        assert_eq!(strided.to_packed(), packed);
+        assert_eq!(strided.regions(), packed.as_user_map().regions());
+        assert_eq!(strided.user_ids(), vec![1, 3]);
+        assert_eq!(strided.mask(0), packed.as_user_map().mask(0));
+    }
+
+    #[test]
+    fn filter_masks_keeps_overlaps_with_their_owner() {
+        let map = OwnedUserMap::new(4, 2, vec![1, 1, 0, 2, 1, 0, 0, 2]);
+        let filtered = map.as_user_map().filter_masks(|mask| mask.dilate(1));
+        assert_eq!(filtered.pixels(), &[1, 1, 1, 2, 1, 1, 1, 2]);
+
+        let unchanged = map.as_user_map().filter_masks(|mask| mask.clone());
+        assert_eq!(unchanged, map);
+    }
+}
@@ -2,17 +2,26 @@ use std::os::raw::{c_ulonglong, c_int, c_void};
 use std::marker::PhantomData;
 use std::{ptr, slice};
 use skeleton::Skeleton;
-use types::{Status, SkeletonState, WorldPoint, DepthPoint};
+use user_map::UserMap;
+use device::CppDevice;
+use types::{Status, SkeletonState, PoseType, UserState, WorldPoint, DepthPoint};
 use openni2::{
+    Device,
     Frame,
     OniDepthPixel,
     frame_from_pointer as oni_frame_from_pointer,
 };
 use nite2_sys::*;
 
+pub(crate) const NITE_POSE_STATE_DETECTED: c_int = 1;
+pub(crate) const NITE_POSE_STATE_IN_POSE: c_int = 2;
+pub(crate) const NITE_POSE_STATE_ENTER: c_int = 4;
+pub(crate) const NITE_POSE_STATE_EXIT: c_int = 8;
+
 pub struct UserTracker<'a> {
     handle: NiteUserTrackerHandle,
-    _device_lifetime: PhantomData<&'a ()>,
+    _device: Option<CppDevice>,
+    _device_lifetime: PhantomData<&'a Device>,
 }
 
 impl<'a> UserTracker<'a> {
@@ -24,13 +33,32 @@ impl<'a> UserTracker<'a> {
         match status {
             Status::Ok => Ok(UserTracker {
                 handle,
+                _device: None,
                 _device_lifetime: PhantomData,
             }),
             _ => Err(status),
         }
     }
 
-    pub fn read_frame(&self) -> Result<UserTrackerFrame<'a>, Status> {
+    /// Tracks users on an already opened device, which may be a live sensor
+    /// or an `.oni` recording.
+    pub fn open(device: &'a Device) -> Result<UserTracker<'a>, Status> {
+        let mut handle: NiteUserTrackerHandle = ptr::null_mut();
+        let cpp_device = CppDevice::new(device)?;
+        let status = unsafe {
+            niteInitializeUserTrackerByDevice(cpp_device.as_ptr(), &mut handle)
+        }.into();
+        match status {
+            Status::Ok => Ok(UserTracker {
+                handle,
+                _device: Some(cpp_device),
+                _device_lifetime: PhantomData,
+            }),
+            _ => Err(status),
+        }
+    }
+
+    pub fn read_frame(&self) -> Result<UserTrackerFrame<'_>, Status> {
         let mut pointer = ptr::null_mut();
         let status = unsafe {
             niteReadUserTrackerFrame(self.handle, &mut pointer)
@@ -59,38 +87,78 @@ impl<'a> UserTracker<'a> {
         unsafe { niteIsSkeletonTracking(self.handle, user) }
     }
 
-    // niteSetSkeletonSmoothing
-    // niteGetSkeletonSmoothing
-    // niteStartPoseDetection
-    // niteStopPoseDetection
-    // niteStopAllPoseDetection
+    /// Sets the skeleton smoothing factor, from 0.0 (no smoothing) up to,
+    /// but not including, 1.0 (joints never move).
+    pub fn set_skeleton_smoothing(&self, factor: f32) -> Result<(), Status> {
+        if !(0.0..1.0).contains(&factor) {
+            return Err(Status::Error(format!("Skeleton smoothing factor {} is outside of [0.0, 1.0)", factor)));
+        }
+        let status = unsafe { niteSetSkeletonSmoothing(self.handle, factor) }.into();
+        if let Status::Ok = status {
+            Ok(())
+        } else {
+            Err(status)
+        }
+    }
+
+    pub fn skeleton_smoothing(&self) -> Result<f32, Status> {
+        let mut factor = 0.0;
+        let status = unsafe { niteGetSkeletonSmoothing(self.handle, &mut factor) }.into();
+        match status {
+            Status::Ok => Ok(factor),
+            _ => Err(status),
+        }
+    }
+
+    pub fn start_pose_detection(&self, user: NiteUserId, pose: PoseType) -> Result<(), Status> {
+        let status = unsafe {
+            niteStartPoseDetection(self.handle, user, pose as NitePoseType)
+        }.into();
+        if let Status::Ok = status {
+            Ok(())
+        } else {
+            Err(status)
+        }
+    }
+
+    pub fn stop_pose_detection(&self, user: NiteUserId, pose: PoseType) {
+        unsafe { niteStopPoseDetection(self.handle, user, pose as NitePoseType); }
+    }
 
-    pub fn register_next_frame_callback<F: FnMut(&UserTracker)>(&self, mut callback: F) -> Result<UserTrackerListener, Status> {
+    pub fn stop_all_pose_detection(&self, user: NiteUserId) {
+        unsafe { niteStopAllPoseDetection(self.handle, user); }
+    }
+
+    pub fn register_next_frame_callback<'s, F: FnMut(&UserTracker) + 's>(&'s self, mut callback: F) -> Result<UserTrackerListener<'s>, Status> {
         extern "C" fn callback_wrapper(cookie: *mut c_void) {
             let closure: &mut dyn FnMut() = unsafe { &mut *(cookie as *mut Box<dyn FnMut()>) };
             closure();
         }
 
-        let closure: Box<Box<dyn FnMut()>> = Box::new(Box::new(move || {
-            callback(&self);
+        // NiTE keeps both pointers until the callbacks are unregistered, so
+        // they're boxed and owned by the listener, which frees them after that.
+        let closure: Box<Box<dyn FnMut() + 's>> = Box::new(Box::new(move || {
+            callback(self);
         }));
-
-        let mut callback_struct = NiteUserTrackerCallbacks {
+        let closure = Box::into_raw(closure);
+        let mut callback_struct = Box::new(NiteUserTrackerCallbacks {
             readyForNextFrame: Some(callback_wrapper),
-        };
+        });
         let status = unsafe {
             niteRegisterUserTrackerCallbacks(
                 self.handle,
-                &mut callback_struct,
-                Box::into_raw(closure) as *mut _,
+                &mut *callback_struct,
+                closure as *mut _,
             )
         }.into();
         if let Status::Ok = status {
             Ok(UserTrackerListener {
                 user_tracker_handle: &self.handle,
                 callback_struct,
+                closure,
             })
         } else {
+            drop(unsafe { Box::from_raw(closure) });
             Err(status)
         }
     }
@@ -146,12 +214,8 @@ impl<'a> UserTrackerFrame<'a> {
         let width = self.nite_frame.userMap.width as usize;
         let height = self.nite_frame.userMap.height as usize;
         let stride = self.nite_frame.userMap.stride as usize;
-        UserMap {
-            pixels: unsafe { slice::from_raw_parts(pixels, width * height) },
-            width,
-            height,
-            stride,
-        }
+        let len = UserMap::required_len(width, height, stride);
+        UserMap::new(unsafe { slice::from_raw_parts(pixels, len) }, width, height, stride)
     }
 
     pub fn users(&self) -> Vec<UserData> {
@@ -179,37 +243,53 @@ impl<'a> Drop for UserTrackerFrame<'a> {
     }
 }
 
-pub struct UserMap<'a> {
-    pub pixels: &'a [NiteUserId],
-    pub width: usize,
-    pub height: usize,
-    pub stride: usize,
-}
-
 pub struct UserTrackerListener<'tracker> {
     user_tracker_handle: &'tracker NiteUserTrackerHandle,
-    callback_struct: NiteUserTrackerCallbacks,
+    callback_struct: Box<NiteUserTrackerCallbacks>,
+    closure: *mut Box<dyn FnMut() + 'tracker>,
 }
 
 impl<'tracker> Drop for UserTrackerListener<'tracker> {
     fn drop(&mut self) {
         unsafe {
-            niteUnregisterUserTrackerCallbacks(*self.user_tracker_handle, &mut self.callback_struct);
+            niteUnregisterUserTrackerCallbacks(*self.user_tracker_handle, &mut *self.callback_struct);
+            drop(Box::from_raw(self.closure));
         }
     }
 }
 
 #[derive(Clone, Copy, Debug)]
-pub struct UserData(NiteUserData);
+pub struct UserData(pub(crate) NiteUserData);
+
+impl From<NiteUserData> for UserData {
+    fn from(user_data: NiteUserData) -> UserData {
+        UserData(user_data)
+    }
+}
 
 impl UserData {
     pub fn id(&self) -> NiteUserId {
         self.0.id
     }
 
-    // TODO: used?
-    pub fn state(&self) -> c_int {
-        self.0.state
+    pub fn state(&self) -> UserState {
+        self.0.state.into()
+    }
+
+    pub fn is_new(&self) -> bool {
+        self.state().contains(UserState::NEW)
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.state().contains(UserState::VISIBLE)
+    }
+
+    pub fn is_lost(&self) -> bool {
+        self.state().contains(UserState::LOST)
+    }
+
+    pub fn skeleton_state(&self) -> SkeletonState {
+        self.0.skeleton.state.into()
     }
 
     pub fn skeleton(&self) -> Result<Skeleton, SkeletonState> {
@@ -227,5 +307,32 @@ impl UserData {
         (self.0.boundingBox.min.into(), self.0.boundingBox.max.into())
     }
 
-    // pub fn poses
+    pub fn pose(&self, pose: PoseType) -> PoseData {
+        PoseData(self.0.poses[pose as usize])
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct PoseData(NitePoseData);
+
+impl PoseData {
+    pub fn pose_type(&self) -> PoseType {
+        self.0.type_.into()
+    }
+
+    pub fn is_detected(&self) -> bool {
+        self.0.state & NITE_POSE_STATE_DETECTED != 0
+    }
+
+    pub fn is_held(&self) -> bool {
+        self.0.state & NITE_POSE_STATE_IN_POSE != 0
+    }
+
+    pub fn is_entered(&self) -> bool {
+        self.0.state & NITE_POSE_STATE_ENTER != 0
+    }
+
+    pub fn is_exited(&self) -> bool {
+        self.0.state & NITE_POSE_STATE_EXIT != 0
+    }
 }
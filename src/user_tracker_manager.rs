@@ -1,54 +1,429 @@
+use std::collections::BTreeMap;
 use nite2_sys::NiteUserId;
-use types::{Status};
-use user_tracker::{UserTracker, UserData, UserTrackerFrame};
+use openni2::Device;
+use types::{Status, SkeletonState};
+use user_tracker::{UserTracker, UserData};
+use frame_source::{FrameSource, TrackerFrame};
 
-pub struct UserTrackerManager<'a> {
-    user_tracker: UserTracker<'a>,
-    users: Vec<UserData>,
-    seen_user_ids: Vec<NiteUserId>,
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum UserEvent {
+    UserEntered(NiteUserId),
+    UserLeft(NiteUserId),
+    UserVisible(NiteUserId),
+    UserOutOfView(NiteUserId),
+    CalibrationStarted(NiteUserId),
+    CalibrationFailed(NiteUserId, SkeletonState),
+    SkeletonTracked(NiteUserId),
+    SkeletonLost(NiteUserId),
+}
+
+impl UserEvent {
+    pub fn user_id(&self) -> NiteUserId {
+        match *self {
+            UserEvent::UserEntered(id) => id,
+            UserEvent::UserLeft(id) => id,
+            UserEvent::UserVisible(id) => id,
+            UserEvent::UserOutOfView(id) => id,
+            UserEvent::CalibrationStarted(id) => id,
+            UserEvent::CalibrationFailed(id, _) => id,
+            UserEvent::SkeletonTracked(id) => id,
+            UserEvent::SkeletonLost(id) => id,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct UserRecord {
+    id: NiteUserId,
+    first_seen_timestamp: u64,
+    first_seen_frame: usize,
+    last_seen_timestamp: u64,
+    last_seen_frame: usize,
+    visible: bool,
+    departed: bool,
+    skeleton_state: SkeletonState,
+    skeleton_requested: bool,
+}
+
+impl UserRecord {
+    pub fn id(&self) -> NiteUserId {
+        self.id
+    }
+
+    pub fn first_seen_timestamp(&self) -> u64 {
+        self.first_seen_timestamp
+    }
+
+    pub fn first_seen_frame(&self) -> usize {
+        self.first_seen_frame
+    }
+
+    /// The last frame the user was in, which stays put once they've left.
+    pub fn last_seen_timestamp(&self) -> u64 {
+        self.last_seen_timestamp
+    }
+
+    pub fn last_seen_frame(&self) -> usize {
+        self.last_seen_frame
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    /// Whether the user has left. Departed users stay in the registry until
+    /// `UserTrackerManager::prune_departed`, or until NiTE reuses their id.
+    pub fn has_departed(&self) -> bool {
+        self.departed
+    }
+
+    pub fn skeleton_state(&self) -> SkeletonState {
+        self.skeleton_state
+    }
+
+    pub fn skeleton_requested(&self) -> bool {
+        self.skeleton_requested
+    }
+}
+
+pub struct UserTrackerManager<S: FrameSource> {
+    user_tracker: S,
+    registry: BTreeMap<NiteUserId, UserRecord>,
     track_skeletons: bool,
 }
 
-impl<'a> UserTrackerManager<'a> {
+impl<'a> UserTrackerManager<UserTracker<'a>> {
+    pub fn create() -> Result<UserTrackerManager<UserTracker<'a>>, Status> {
+        Ok(UserTrackerManager::from_source(UserTracker::open_default()?))
+    }
 
-    pub fn create() -> Result<UserTrackerManager<'a>, Status> {
-        let manager = UserTrackerManager {
-            user_tracker: UserTracker::open_default()?,
-            users: Vec::with_capacity(10),
-            seen_user_ids: Vec::with_capacity(10),
-            track_skeletons: true,
-        };
+    pub fn open(device: &'a Device) -> Result<UserTrackerManager<UserTracker<'a>>, Status> {
+        Ok(UserTrackerManager::from_source(UserTracker::open(device)?))
+    }
+
+    pub fn create_with_smoothing(skeleton_smoothing: f32) -> Result<UserTrackerManager<UserTracker<'a>>, Status> {
+        let manager = UserTrackerManager::create()?;
+        manager.set_skeleton_smoothing(skeleton_smoothing)?;
         Ok(manager)
     }
 
+    pub fn set_skeleton_smoothing(&self, factor: f32) -> Result<(), Status> {
+        self.user_tracker.set_skeleton_smoothing(factor)
+    }
+
+    pub fn skeleton_smoothing(&self) -> Result<f32, Status> {
+        self.user_tracker.skeleton_smoothing()
+    }
+}
+
+impl<S: FrameSource> UserTrackerManager<S> {
+    pub fn from_source(source: S) -> UserTrackerManager<S> {
+        UserTrackerManager {
+            user_tracker: source,
+            registry: BTreeMap::new(),
+            track_skeletons: true,
+        }
+    }
+
+    pub fn source(&self) -> &S {
+        &self.user_tracker
+    }
+
+    /// Starts or stops skeleton tracking for every current user, and for
+    /// users who enter later. Every user is tried even if some fail, and the
+    /// first failure is returned.
     pub fn track_skeletons(&mut self, enable: bool) -> Result<(), Status> {
-        for id in &self.seen_user_ids {
-            self.user_tracker.track_skeleton(*id, enable)?;
+        let mut result = Ok(());
+        for record in self.registry.values_mut().filter(|record| !record.departed) {
+            match self.user_tracker.track_skeleton(record.id, enable) {
+                Ok(()) => record.skeleton_requested = enable,
+                Err(e) => if result.is_ok() {
+                    result = Err(e);
+                },
+            }
         }
         self.track_skeletons = enable;
-        Ok(())
+        result
     }
 
-    pub fn read_frame(&mut self) -> Result<UserTrackerFrame, Status> {
+    pub fn read_frame(&mut self) -> Result<(S::Frame<'_>, Vec<UserEvent>), Status> {
         let frame = self.user_tracker.read_frame()?;
+        let timestamp = frame.timestamp();
+        let frame_index = frame.frame_index();
         let users = frame.users();
-        if self.track_skeletons {
-            for user in &users {
-                let user_id = user.id();
-                if !self.users.iter().any(|u| u.id() == user_id) {
-                    match self.user_tracker.track_skeleton(user_id, true) {
-                        Ok(_) => println!("Tracking skeleton for user {}", user_id),
-                        Err(e) => println!("Couldn't track skeleton for user {}: {}", user_id, e),
-                    }
+        let mut events = Vec::new();
+
+        for user in &users {
+            let id = user.id();
+            let known = self.registry.get(&id).is_some_and(|record| !record.departed);
+            if !known {
+                if user.is_lost() {
+                    continue;
                 }
+                self.registry.insert(id, UserRecord {
+                    id,
+                    first_seen_timestamp: timestamp,
+                    first_seen_frame: frame_index,
+                    last_seen_timestamp: timestamp,
+                    last_seen_frame: frame_index,
+                    visible: false,
+                    departed: false,
+                    skeleton_state: SkeletonState::None,
+                    skeleton_requested: false,
+                });
+                events.push(UserEvent::UserEntered(id));
+            }
+            let record = self.registry.get_mut(&id).expect("user is registered");
+            // A request that NiTE turned down is tried again every frame
+            if self.track_skeletons && !record.skeleton_requested && !user.is_lost() {
+                record.skeleton_requested = self.user_tracker.track_skeleton(id, true).is_ok();
+            }
+            user_events(record, user, &mut events);
+            record.last_seen_timestamp = timestamp;
+            record.last_seen_frame = frame_index;
+            record.visible = user.is_visible();
+            record.skeleton_state = user.skeleton_state();
+        }
+
+        // NiTE flags a user as lost for a single frame, but don't count on
+        // having seen that frame.
+        for record in self.registry.values_mut().filter(|record| !record.departed) {
+            if !users.iter().any(|u| u.id() == record.id && !u.is_lost()) {
+                if record.skeleton_state == SkeletonState::Tracked {
+                    events.push(UserEvent::SkeletonLost(record.id));
+                }
+                record.departed = true;
+                record.visible = false;
+                record.skeleton_state = SkeletonState::None;
+                record.skeleton_requested = false;
+                events.push(UserEvent::UserLeft(record.id));
+            }
+        }
+
+        Ok((frame, events))
+    }
+
+    pub fn user(&self, id: NiteUserId) -> Option<&UserRecord> {
+        self.registry.get(&id)
+    }
+
+    /// Every user in the registry, including departed ones, ordered by id.
+    pub fn users(&self) -> impl Iterator<Item = &UserRecord> {
+        self.registry.values()
+    }
+
+    /// Forgets users who have left.
+    pub fn prune_departed(&mut self) {
+        self.registry.retain(|_, record| !record.departed);
+    }
+}
+
+fn user_events(record: &UserRecord, user: &UserData, events: &mut Vec<UserEvent>) {
+    let id = user.id();
+    if user.is_visible() && !record.visible {
+        events.push(UserEvent::UserVisible(id));
+    }
+
+    let state = user.skeleton_state();
+    if state != record.skeleton_state {
+        if record.skeleton_state == SkeletonState::Tracked {
+            events.push(UserEvent::SkeletonLost(id));
+        }
+        match state {
+            SkeletonState::None => {},
+            SkeletonState::Calibrating => events.push(UserEvent::CalibrationStarted(id)),
+            SkeletonState::Tracked => events.push(UserEvent::SkeletonTracked(id)),
+            failure => events.push(UserEvent::CalibrationFailed(id, failure)),
+        }
+    }
+
+    if !user.is_visible() && record.visible {
+        events.push(UserEvent::UserOutOfView(id));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use frame_source::OwnedFrame;
+    use mock::{MockFrameSource, MockUser};
+    use types::UserState;
+
+    fn frame(frame_index: usize, users: Vec<MockUser>) -> OwnedFrame {
+        let mut frame = OwnedFrame::new(frame_index, frame_index as u64 * 33_333);
+        frame.users = users.into_iter().map(UserData::from).collect();
+        frame
+    }
+
+    fn events<S: FrameSource>(manager: &mut UserTrackerManager<S>) -> Vec<UserEvent> {
+        manager.read_frame().expect("mock frame").1
+    }
+
+    #[test]
+    fn user_lifecycle() {
+        let new = UserState::NEW | UserState::VISIBLE;
+        let source = MockFrameSource::new(vec![
+            frame(0, vec![MockUser::new(1).state(new)]),
+            frame(1, vec![MockUser::new(1).skeleton_state(SkeletonState::Calibrating)]),
+            frame(2, vec![MockUser::new(1).skeleton_state(SkeletonState::Tracked)]),
+            frame(3, vec![MockUser::new(1).state(UserState::from(0)).skeleton_state(SkeletonState::Tracked)]),
+            frame(4, vec![MockUser::new(1).skeleton_state(SkeletonState::None)]),
+            frame(5, vec![MockUser::new(1).state(UserState::LOST)]),
+            frame(6, vec![]),
+        ]);
+        let mut manager = UserTrackerManager::from_source(source);
+
+        assert_eq!(events(&mut manager), vec![UserEvent::UserEntered(1), UserEvent::UserVisible(1)]);
+        assert!(manager.source().tracking_skeleton(1));
+        {
+            let record = manager.user(1).expect("user 1 is registered");
+            assert_eq!(record.first_seen_frame(), 0);
+            assert!(record.is_visible());
+            assert!(record.skeleton_requested());
+            assert!(!record.has_departed());
+        }
+
+        assert_eq!(events(&mut manager), vec![UserEvent::CalibrationStarted(1)]);
+        assert_eq!(manager.user(1).map(|record| record.skeleton_state()), Some(SkeletonState::Calibrating));
+        assert_eq!(events(&mut manager), vec![UserEvent::SkeletonTracked(1)]);
+        assert_eq!(events(&mut manager), vec![UserEvent::UserOutOfView(1)]);
+        assert_eq!(events(&mut manager), vec![UserEvent::UserVisible(1), UserEvent::SkeletonLost(1)]);
+        assert_eq!(events(&mut manager), vec![UserEvent::UserOutOfView(1), UserEvent::UserLeft(1)]);
+        assert_eq!(events(&mut manager), vec![]);
+
+        let record = manager.user(1).expect("departed users stay registered");
+        assert!(record.has_departed());
+        assert!(!record.is_visible());
+        assert_eq!(record.first_seen_timestamp(), 0);
+        assert_eq!(record.last_seen_frame(), 5);
+        assert_eq!(record.last_seen_timestamp(), 5 * 33_333);
+
+        manager.prune_departed();
+        assert!(manager.user(1).is_none());
+        assert_eq!(manager.users().count(), 0);
+    }
+
+    #[test]
+    fn user_leaves_without_lost_frame() {
+        let source = MockFrameSource::new(vec![
+            frame(0, vec![MockUser::new(1), MockUser::new(2)]),
+            frame(1, vec![MockUser::new(2)]),
+            frame(2, vec![MockUser::new(1), MockUser::new(2)]),
+        ]);
+        let mut manager = UserTrackerManager::from_source(source);
+
+        assert_eq!(events(&mut manager), vec![
+            UserEvent::UserEntered(1), UserEvent::UserVisible(1),
+            UserEvent::UserEntered(2), UserEvent::UserVisible(2),
+        ]);
+        assert_eq!(events(&mut manager), vec![UserEvent::UserLeft(1)]);
+        assert_eq!(manager.users().map(|record| (record.id(), record.has_departed())).collect::<Vec<_>>(),
+            vec![(1, true), (2, false)]);
+
+        // NiTE reuses ids, which start a new record
+        assert_eq!(events(&mut manager), vec![UserEvent::UserEntered(1), UserEvent::UserVisible(1)]);
+        let record = manager.user(1).expect("user 1 is registered");
+        assert!(!record.has_departed());
+        assert_eq!(record.first_seen_frame(), 2);
+    }
+
+    #[test]
+    fn lost_user_is_never_entered() {
+        let source = MockFrameSource::new(vec![
+            frame(0, vec![MockUser::new(3).state(UserState::LOST)]),
+        ]);
+        let mut manager = UserTrackerManager::from_source(source);
+
+        assert_eq!(events(&mut manager), vec![]);
+        assert!(manager.user(3).is_none());
+    }
+
+    #[test]
+    fn toggle_skeleton_tracking() {
+        let source = MockFrameSource::new(vec![
+            frame(0, vec![MockUser::new(1)]),
+            frame(1, vec![MockUser::new(1), MockUser::new(2)]),
+        ]);
+        let mut manager = UserTrackerManager::from_source(source);
+        events(&mut manager);
+
+        manager.track_skeletons(false).expect("mock never fails");
+        assert!(!manager.source().tracking_skeleton(1));
+        assert!(!manager.user(1).expect("user 1 is registered").skeleton_requested());
+
+        events(&mut manager);
+        assert!(!manager.source().tracking_skeleton(2));
+        assert!(!manager.user(2).expect("user 2 is registered").skeleton_requested());
+
+        manager.track_skeletons(true).expect("mock never fails");
+        assert!(manager.source().tracking_skeleton(1));
+        assert!(manager.source().tracking_skeleton(2));
+    }
+
+    #[test]
+    fn tracked_user_leaving_loses_skeleton() {
+        let source = MockFrameSource::new(vec![
+            frame(0, vec![MockUser::new(1).skeleton_state(SkeletonState::Tracked)]),
+            frame(1, vec![]),
+        ]);
+        let mut manager = UserTrackerManager::from_source(source);
+        events(&mut manager);
+
+        assert_eq!(events(&mut manager), vec![UserEvent::SkeletonLost(1), UserEvent::UserLeft(1)]);
+        assert_eq!(manager.user(1).map(|record| record.skeleton_state()), Some(SkeletonState::None));
+    }
+
+    // Turns down the first `refusals` skeleton tracking requests
+    struct RefusingSource {
+        source: MockFrameSource,
+        refusals: Cell<usize>,
+    }
+
+    impl FrameSource for RefusingSource {
+        type Frame<'s> = OwnedFrame where Self: 's;
+
+        fn read_frame(&self) -> Result<OwnedFrame, Status> {
+            self.source.read_frame()
+        }
+
+        fn track_skeleton(&self, user: NiteUserId, setting: bool) -> Result<(), Status> {
+            if setting && self.refusals.get() > 0 {
+                self.refusals.set(self.refusals.get() - 1);
+                return Err(Status::BadUserId);
             }
+            self.source.track_skeleton(user, setting)
+        }
+
+        fn tracking_skeleton(&self, user: NiteUserId) -> bool {
+            self.source.tracking_skeleton(user)
         }
-        self.users = users;
+    }
+
+    #[test]
+    fn refused_skeleton_tracking_is_retried() {
+        let source = RefusingSource {
+            source: MockFrameSource::new(vec![
+                frame(0, vec![MockUser::new(1)]),
+                frame(1, vec![MockUser::new(1)]),
+                frame(2, vec![MockUser::new(1)]),
+            ]),
+            refusals: Cell::new(2),
+        };
+        let mut manager = UserTrackerManager::from_source(source);
 
-        Ok(frame)
+        events(&mut manager);
+        assert!(!manager.user(1).expect("user 1 is registered").skeleton_requested());
+        events(&mut manager);
+        assert!(!manager.source().tracking_skeleton(1));
+        events(&mut manager);
+        assert!(manager.user(1).expect("user 1 is registered").skeleton_requested());
+        assert!(manager.source().tracking_skeleton(1));
     }
 
-    pub fn seen_user_ids(&self) -> &[NiteUserId] {
-        &self.seen_user_ids
+    #[test]
+    fn out_of_frames() {
+        let mut manager = UserTrackerManager::from_source(MockFrameSource::new(vec![]));
+        assert_eq!(manager.read_frame().err(), Some(Status::OutOfFlow));
     }
 }